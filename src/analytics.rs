@@ -0,0 +1,241 @@
+use crate::export::HISTORY_DATE_FORMAT;
+use crate::models::MessageSent;
+use crate::phone::normalize_number;
+use chrono::NaiveDateTime;
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+
+/// Digits of a normalised number that identify its operator, e.g. `254712` (country code plus
+/// the first three digits of the subscriber number)
+pub const OPERATOR_PREFIX_LEN: usize = 6;
+
+/// `DeliveryStats` counts messages by outcome for one group
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryStats {
+    pub messages: u64,
+    pub delivered: u64,
+    /// Rejected for a blacklisted or invalid number
+    pub failed: u64,
+    /// Neither delivered nor rejected, e.g. still pending or expired
+    pub other: u64,
+    /// Sum of `message_count`
+    pub credits: u64,
+    /// `delivered / messages`, or 0 for an empty group
+    pub delivery_rate: f64,
+}
+
+impl DeliveryStats {
+    fn record(&mut self, message: &MessageSent) {
+        self.messages += 1;
+        if message.is_delivered() {
+            self.delivered += 1;
+        } else if message.is_failed() {
+            self.failed += 1;
+        } else {
+            self.other += 1;
+        }
+        self.credits += message.message_count.max(0) as u64;
+        self.delivery_rate = self.delivered as f64 / self.messages as f64;
+    }
+}
+
+/// `DeliveryReport` is the output of `DeliveryAnalytics`. Map keys are sorted, so a report
+/// serializes the same way every time.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryReport {
+    pub overall: DeliveryStats,
+    pub by_sender: BTreeMap<String, DeliveryStats>,
+    /// Keyed by the first `OPERATOR_PREFIX_LEN` digits of the normalised number
+    pub by_operator_prefix: BTreeMap<String, DeliveryStats>,
+    /// Keyed by the `created_at` date, `YYYY-MM-DD`
+    pub by_day: BTreeMap<String, DeliveryStats>,
+    /// Status of every failed message (see `DeliveryStats::failed`) and how often it occurred
+    pub failure_reasons: BTreeMap<String, u64>,
+    /// Total `message_count` across all messages
+    pub credits_used: u64,
+    /// Median of `updated_at - created_at` over delivered messages
+    pub median_delivery_latency_secs: Option<f64>,
+}
+
+/// `DeliveryAnalytics` accumulates `MessageSent` rows into a `DeliveryReport`.
+/// Feed it from an iterator with `extend` or `collect`, or from a stream with `record_stream`.
+#[derive(Debug, Clone, Default)]
+pub struct DeliveryAnalytics {
+    report: DeliveryReport,
+    latencies: Vec<i64>,
+}
+
+impl DeliveryAnalytics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, message: &MessageSent) {
+        let report = &mut self.report;
+        report.overall.record(message);
+        report
+            .by_sender
+            .entry(message.sender_id.clone())
+            .or_default()
+            .record(message);
+
+        let number = normalize_number(&message.number);
+        let prefix = number.get(..OPERATOR_PREFIX_LEN).unwrap_or(&number);
+        report
+            .by_operator_prefix
+            .entry(prefix.to_string())
+            .or_default()
+            .record(message);
+
+        let created = NaiveDateTime::parse_from_str(&message.created_at, HISTORY_DATE_FORMAT).ok();
+        if let Some(created) = created {
+            report
+                .by_day
+                .entry(created.date().to_string())
+                .or_default()
+                .record(message);
+        }
+
+        if message.is_failed() {
+            *report
+                .failure_reasons
+                .entry(message.status.clone())
+                .or_default() += 1;
+        }
+        report.credits_used = report.overall.credits;
+
+        let updated = NaiveDateTime::parse_from_str(&message.updated_at, HISTORY_DATE_FORMAT).ok();
+        if let (true, Some(created), Some(updated)) = (message.is_delivered(), created, updated) {
+            let latency = (updated - created).num_seconds();
+            if latency >= 0 {
+                self.latencies.push(latency);
+            }
+        }
+    }
+
+    /// Records every message of `stream`, e.g. rows read from a database or a channel
+    pub async fn record_stream<S>(&mut self, stream: S)
+    where
+        S: Stream,
+        S::Item: Borrow<MessageSent>,
+    {
+        let mut stream = std::pin::pin!(stream);
+        while let Some(message) = stream.next().await {
+            self.record(message.borrow());
+        }
+    }
+
+    /// The report for everything recorded so far
+    pub fn report(&self) -> DeliveryReport {
+        let mut report = self.report.clone();
+        report.median_delivery_latency_secs = median(&self.latencies);
+        report
+    }
+}
+
+impl<M: Borrow<MessageSent>> Extend<M> for DeliveryAnalytics {
+    fn extend<I: IntoIterator<Item = M>>(&mut self, messages: I) {
+        for message in messages {
+            self.record(message.borrow());
+        }
+    }
+}
+
+impl<M: Borrow<MessageSent>> FromIterator<M> for DeliveryAnalytics {
+    fn from_iter<I: IntoIterator<Item = M>>(messages: I) -> Self {
+        let mut analytics = DeliveryAnalytics::new();
+        analytics.extend(messages);
+        analytics
+    }
+}
+
+fn median(values: &[i64]) -> Option<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let mid = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        n if n % 2 == 1 => Some(sorted[mid] as f64),
+        _ => Some((sorted[mid - 1] + sorted[mid]) as f64 / 2.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sent;
+
+    #[test]
+    fn test_report_groups_and_latency() {
+        let messages = vec![
+            sent(1)
+                .sender("ACME")
+                .created_at("2025-07-20 10:00:00")
+                .updated_at("2025-07-20 10:00:04")
+                .build(),
+            sent(2)
+                .number("0712000000")
+                .sender("ACME")
+                .created_at("2025-07-20 11:00:00")
+                .updated_at("2025-07-20 11:00:10")
+                .message_count(2)
+                .build(),
+            sent(3)
+                .number("254733111111")
+                .sender("ACME")
+                .status("Blacklisted")
+                .created_at("2025-07-21 09:00:00")
+                .updated_at("2025-07-21 09:00:01")
+                .build(),
+            sent(4)
+                .number("254733222222")
+                .status("DeliveryImpossible")
+                .created_at("2025-07-21 09:30:00")
+                .updated_at("2025-07-21 12:00:00")
+                .build(),
+        ];
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let streamed = rt.block_on(async {
+            let mut analytics = DeliveryAnalytics::new();
+            analytics
+                .record_stream(futures_util::stream::iter(messages.clone()))
+                .await;
+            analytics.report()
+        });
+        let report = messages.iter().collect::<DeliveryAnalytics>().report();
+        assert_eq!(report, streamed);
+
+        assert_eq!(report.overall.messages, 4);
+        assert_eq!(report.overall.delivery_rate, 0.5);
+        assert_eq!(report.credits_used, 5);
+        assert_eq!(report.by_sender["ACME"].delivered, 2);
+        assert_eq!(report.by_sender["ACME"].failed, 1);
+        assert_eq!(report.by_sender["UjumbeSMS"].other, 1);
+        assert_eq!(report.by_operator_prefix["254712"].delivery_rate, 1.0);
+        assert_eq!(report.by_operator_prefix["254733"].delivery_rate, 0.0);
+        assert_eq!(report.by_day["2025-07-20"].credits, 3);
+        assert_eq!(report.by_day["2025-07-21"].messages, 2);
+        // DeliveryImpossible is counted under `other`, not as a failure
+        assert_eq!(
+            report.failure_reasons,
+            BTreeMap::from([("Blacklisted".to_string(), 1)])
+        );
+        // Only delivered messages count: 4s and 10s
+        assert_eq!(report.median_delivery_latency_secs, Some(7.0));
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(
+            serde_json::from_str::<DeliveryReport>(&json).unwrap(),
+            report
+        );
+        assert_eq!(
+            DeliveryAnalytics::new()
+                .report()
+                .median_delivery_latency_secs,
+            None
+        );
+    }
+}
@@ -0,0 +1,499 @@
+use crate::client::UjumbeSmsClient;
+use crate::errors::UjumbeSmsError;
+use crate::export::HISTORY_DATE_FORMAT;
+use crate::models::{MessageRequest, MessageSent, MessagingApiResponse};
+use crate::phone::{is_plausible_number, normalize_number, split_numbers};
+use crate::sending_window::nairobi_now;
+use chrono::{NaiveDateTime, SubsecRound, TimeDelta};
+use std::collections::HashSet;
+
+/// History pages scanned by `UjumbeSmsClient::send_messages_with_report`
+pub const DEFAULT_HISTORY_PAGES: u32 = 3;
+/// Subtracted from `BatchSendReport::sent_at`: rows may be created just before the response is
+/// dated, and the local clock, used when the response has no date, may run ahead of the server's
+pub const SEND_TIME_MARGIN: TimeDelta = TimeDelta::seconds(60);
+
+/// Format of `MessagingMetaInfo.date_time.date`, e.g. `20150815 18:19:47`
+const RESPONSE_DATE_FORMAT: &str = "%Y%m%d %H:%M:%S";
+
+/// `RecipientOutcome` is what happened to one number of a batch send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecipientOutcome {
+    /// Removed by the client before sending
+    Dropped { reason: String },
+    /// Sent, but not (yet) visible in the message history
+    Pending,
+    /// Found in the message history with a non-failure status
+    Accepted {
+        status: String,
+        request_id: i64,
+        transaction_id: String,
+    },
+    /// Found in the message history as blacklisted or invalid
+    Failed {
+        status: String,
+        request_id: i64,
+        transaction_id: String,
+    },
+}
+
+/// `RecipientResult` pairs a number with its outcome
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecipientResult {
+    /// The number as sent, normalised to `254...` form
+    pub number: String,
+    pub outcome: RecipientOutcome,
+}
+
+/// `BagOutcome` lists the recipients of one message bag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BagOutcome {
+    /// Position of the bag in the original `MessageRequest.data`
+    pub index: usize,
+    pub message: String,
+    pub sender: String,
+    /// The history `request_id` of the bag, once found
+    pub request_id: Option<i64>,
+    pub recipients: Vec<RecipientResult>,
+}
+
+/// `BatchSummary` counts recipients by outcome
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchSummary {
+    pub dropped: usize,
+    pub pending: usize,
+    pub accepted: usize,
+    pub failed: usize,
+}
+
+/// `BatchSendReport` maps each bag and number of a request to its outcome.
+/// It combines client-side validation, the messaging response and a message history lookup;
+/// call `reconcile` again later to pick up numbers that were still pending.
+#[derive(Debug)]
+pub struct BatchSendReport {
+    /// The messaging response, or `None` if validation left nothing to send
+    pub response: Option<MessagingApiResponse>,
+    /// Nairobi time the request was sent, to the second, less `SEND_TIME_MARGIN`. Taken from the
+    /// messaging response's `date_time`, or the local clock if it has none. History rows created
+    /// earlier belong to older sends and are ignored by `reconcile`.
+    pub sent_at: Option<NaiveDateTime>,
+    pub bags: Vec<BagOutcome>,
+    /// Why the history lookup made by `send_messages_with_report` failed, leaving recipients
+    /// `Pending`; cleared by the next `reconcile`
+    pub reconcile_error: Option<UjumbeSmsError>,
+}
+
+impl BatchSendReport {
+    /// Validates `request`, dropping blank, malformed and repeated numbers, and returns the
+    /// report together with the request that should actually be sent
+    pub(crate) fn prepare(request: MessageRequest) -> (Self, MessageRequest) {
+        let mut bags = Vec::with_capacity(request.data.len());
+        let mut sendable = MessageRequest::new();
+
+        for (index, container) in request.data.into_iter().enumerate() {
+            let bag = container.message_bag;
+            let mut seen = HashSet::new();
+            let recipients: Vec<RecipientResult> = split_numbers(&bag.numbers)
+                .map(|raw| {
+                    let number = normalize_number(raw);
+                    let outcome = if !is_plausible_number(&number) {
+                        RecipientOutcome::Dropped {
+                            reason: format!("invalid number \"{raw}\""),
+                        }
+                    } else if !seen.insert(number.clone()) {
+                        RecipientOutcome::Dropped {
+                            reason: "duplicate recipient".to_string(),
+                        }
+                    } else {
+                        RecipientOutcome::Pending
+                    };
+                    RecipientResult { number, outcome }
+                })
+                .collect();
+
+            let numbers: Vec<&str> = recipients
+                .iter()
+                .filter(|r| r.outcome == RecipientOutcome::Pending)
+                .map(|r| r.number.as_str())
+                .collect();
+            if !numbers.is_empty() {
+                sendable.add_message_bag(
+                    numbers.join(","),
+                    bag.message.clone(),
+                    bag.sender.clone(),
+                );
+            }
+
+            bags.push(BagOutcome {
+                index,
+                message: bag.message,
+                sender: bag.sender,
+                request_id: None,
+                recipients,
+            });
+        }
+
+        (
+            BatchSendReport {
+                response: None,
+                sent_at: None,
+                bags,
+                reconcile_error: None,
+            },
+            sendable,
+        )
+    }
+
+    /// The `sent_at` of a send answered by `response`, see `sent_at`
+    pub(crate) fn send_time(response: &MessagingApiResponse) -> NaiveDateTime {
+        let server_time = response.meta.as_ref().and_then(|meta| {
+            let date = meta.date_time.date.trim();
+            NaiveDateTime::parse_from_str(date, RESPONSE_DATE_FORMAT)
+                .or_else(|_| NaiveDateTime::parse_from_str(date, HISTORY_DATE_FORMAT))
+                .ok()
+        });
+        server_time.unwrap_or_else(|| nairobi_now().naive_local().trunc_subsecs(0))
+            - SEND_TIME_MARGIN
+    }
+
+    /// Marks `number` in bag `index` as dropped, e.g. when a suppression list removed it before sending
+    pub fn flag_dropped(&mut self, index: usize, number: &str, reason: impl Into<String>) {
+        let number = normalize_number(number);
+        let Some(bag) = self.bags.iter_mut().find(|bag| bag.index == index) else {
+            return;
+        };
+        let outcome = RecipientOutcome::Dropped {
+            reason: reason.into(),
+        };
+        match bag.recipients.iter_mut().find(|r| r.number == number) {
+            Some(recipient) => recipient.outcome = outcome,
+            None => bag.recipients.push(RecipientResult { number, outcome }),
+        }
+    }
+
+    /// Scans up to `max_pages` of the message history (newest first) and updates every
+    /// recipient that was sent. Only entries created at or after `sent_at` match, and once a
+    /// bag's `request_id` is known only entries with that id.
+    pub async fn reconcile(
+        &mut self,
+        client: &UjumbeSmsClient,
+        max_pages: u32,
+    ) -> Result<(), UjumbeSmsError> {
+        self.reconcile_error = None;
+        let mut matched: HashSet<(usize, String)> = HashSet::new();
+        let expected = self
+            .recipients()
+            .filter(|(_, r)| !matches!(r.outcome, RecipientOutcome::Dropped { .. }))
+            .count();
+
+        for page in 1..=max_pages {
+            let history = client.get_messages_history_page(page).await?;
+            for entry in &history.items.data {
+                self.apply(entry, &mut matched);
+            }
+            if matched.len() == expected || history.items.current_page >= history.items.last_page {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the newest history entry for each recipient it matches
+    fn apply(&mut self, entry: &MessageSent, matched: &mut HashSet<(usize, String)>) {
+        if let Some(sent_at) = self.sent_at {
+            let created_at = NaiveDateTime::parse_from_str(&entry.created_at, HISTORY_DATE_FORMAT);
+            if created_at.map_or(true, |created_at| created_at < sent_at) {
+                return;
+            }
+        }
+        let number = normalize_number(&entry.number);
+        for bag in &mut self.bags {
+            if bag.message != entry.message
+                || bag.sender != entry.sender_id
+                || bag.request_id.is_some_and(|id| id != entry.request_id)
+            {
+                continue;
+            }
+            let Some(recipient) = bag.recipients.iter_mut().find(|r| {
+                r.number == number && !matches!(r.outcome, RecipientOutcome::Dropped { .. })
+            }) else {
+                continue;
+            };
+            if !matched.insert((bag.index, number.clone())) {
+                continue;
+            }
+
+            bag.request_id = Some(entry.request_id);
+            recipient.outcome = if entry.is_failed() {
+                RecipientOutcome::Failed {
+                    status: entry.status.clone(),
+                    request_id: entry.request_id,
+                    transaction_id: entry.transaction_id.clone(),
+                }
+            } else {
+                RecipientOutcome::Accepted {
+                    status: entry.status.clone(),
+                    request_id: entry.request_id,
+                    transaction_id: entry.transaction_id.clone(),
+                }
+            };
+            return;
+        }
+    }
+
+    /// Every recipient with the index of its bag
+    pub fn recipients(&self) -> impl Iterator<Item = (usize, &RecipientResult)> {
+        self.bags
+            .iter()
+            .flat_map(|bag| bag.recipients.iter().map(move |r| (bag.index, r)))
+    }
+
+    /// Outcomes recorded for `number`, one per bag it appears in
+    pub fn outcomes_for(&self, number: &str) -> Vec<(usize, &RecipientOutcome)> {
+        let number = normalize_number(number);
+        self.recipients()
+            .filter(|(_, r)| r.number == number)
+            .map(|(index, r)| (index, &r.outcome))
+            .collect()
+    }
+
+    pub fn summary(&self) -> BatchSummary {
+        self.recipients()
+            .fold(BatchSummary::default(), |mut summary, (_, r)| {
+                match r.outcome {
+                    RecipientOutcome::Dropped { .. } => summary.dropped += 1,
+                    RecipientOutcome::Pending => summary.pending += 1,
+                    RecipientOutcome::Accepted { .. } => summary.accepted += 1,
+                    RecipientOutcome::Failed { .. } => summary.failed += 1,
+                }
+                summary
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{history, messaging_body, messaging_body_at, sent};
+    use crate::UjumbeSmsConfig;
+    use mockito::{Matcher, Server};
+
+    fn history_entry(
+        id: i64,
+        number: &str,
+        message: &str,
+        status: &str,
+        created_at: &str,
+    ) -> MessageSent {
+        sent(id)
+            .request_id(700)
+            .number(number)
+            .message(message)
+            .status(status)
+            .created_at(created_at)
+            .build()
+    }
+
+    #[test]
+    fn test_send_messages_with_report() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let server_now = crate::sending_window::nairobi_now().naive_local();
+
+        rt.block_on(async {
+            let sent = server
+                .mock("POST", "/api/messaging")
+                .match_body(Matcher::PartialJson(serde_json::json!({
+                    "data": [
+                        { "message_bag": { "numbers": "254711111111,254722222222,254733333333" } },
+                        { "message_bag": { "numbers": "254744444444" } }
+                    ]
+                })))
+                .with_status(200)
+                .with_body(messaging_body_at(
+                    4,
+                    &server_now.format(RESPONSE_DATE_FORMAT).to_string(),
+                ))
+                .create();
+            // Rows of this send are created when the response is dated; the older rows are
+            // earlier sends of the same text
+            let now = server_now.format(HISTORY_DATE_FORMAT).to_string();
+            let earlier = "2025-07-20 18:18:11";
+            let history = history([
+                history_entry(4, "254722222222", "First", "Blacklisted", &now),
+                history_entry(3, "254711111111", "First", "DeliveredToTerminal", &now),
+                history_entry(2, "254744444444", "Second", "DeliveredToTerminal", earlier),
+                history_entry(1, "254711111111", "First", "Blacklisted", earlier),
+            ]);
+            let _history = server
+                .mock("POST", "/api/messages")
+                .match_query(Matcher::UrlEncoded("page".into(), "1".into()))
+                .with_status(200)
+                .with_body(serde_json::to_string(&history).unwrap())
+                .create();
+
+            let mut request = MessageRequest::new();
+            request.add_message_bag(
+                "0711111111,254722222222,not-a-number,254733333333,+254722222222".to_string(),
+                "First".to_string(),
+                "UjumbeSMS".to_string(),
+            );
+            request.add_message_bag(
+                "254744444444".to_string(),
+                "Second".to_string(),
+                "UjumbeSMS".to_string(),
+            );
+            request.add_message_bag(
+                "12".to_string(),
+                "Third".to_string(),
+                "UjumbeSMS".to_string(),
+            );
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let client = UjumbeSmsClient::new(config).unwrap();
+            let report = client.send_messages_with_report(request).await.unwrap();
+            sent.assert();
+
+            assert!(report.response.is_some());
+            assert_eq!(
+                report.summary(),
+                BatchSummary {
+                    dropped: 3,
+                    pending: 2,
+                    accepted: 1,
+                    failed: 1
+                }
+            );
+            assert_eq!(report.bags[0].request_id, Some(700));
+            assert_eq!(
+                report.outcomes_for("0711111111"),
+                vec![(
+                    0,
+                    &RecipientOutcome::Accepted {
+                        status: "DeliveredToTerminal".to_string(),
+                        request_id: 700,
+                        transaction_id: "t3".to_string()
+                    }
+                )]
+            );
+            assert!(matches!(
+                report.outcomes_for("254722222222")[..],
+                [
+                    (0, RecipientOutcome::Failed { .. }),
+                    (0, RecipientOutcome::Dropped { .. })
+                ]
+            ));
+            assert_eq!(
+                report.outcomes_for("254733333333"),
+                vec![(0, &RecipientOutcome::Pending)]
+            );
+            // Only an earlier send of "Second" is in the history, so this one is still pending
+            assert_eq!(
+                report.outcomes_for("254744444444"),
+                vec![(1, &RecipientOutcome::Pending)]
+            );
+            assert_eq!(report.bags[1].request_id, None);
+            assert!(matches!(
+                &report.bags[2].recipients[0].outcome,
+                RecipientOutcome::Dropped { reason } if reason.contains("invalid number")
+            ));
+        });
+    }
+
+    #[test]
+    fn test_failed_history_lookup_is_reported() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let _sent = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_body(messaging_body(1))
+                .create();
+            let _history = server
+                .mock("POST", "/api/messages")
+                .match_query(Matcher::Any)
+                .with_status(500)
+                .with_body("Internal Server Error")
+                .create();
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let client = UjumbeSmsClient::new(config).unwrap();
+            let report = client
+                .send_messages_with_report(
+                    MessageRequest::builder()
+                        .bag(|b| b.to("254711111111").text("Hello").from("UjumbeSMS"))
+                        .build()
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert!(report.response.is_some());
+            assert_eq!(report.summary().pending, 1);
+            assert!(matches!(
+                report.reconcile_error,
+                Some(UjumbeSmsError::ApiError(code, _)) if code.starts_with("500")
+            ));
+        });
+    }
+
+    #[test]
+    fn test_server_clock_behind_local_clock() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        // The server dates both the response and the history rows 30 seconds before the local clock
+        let server_now =
+            crate::sending_window::nairobi_now().naive_local() - TimeDelta::seconds(30);
+
+        rt.block_on(async {
+            let _sent = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_body(messaging_body_at(
+                    1,
+                    &server_now.format(RESPONSE_DATE_FORMAT).to_string(),
+                ))
+                .create();
+            let created_at = server_now.format(HISTORY_DATE_FORMAT).to_string();
+            let history = history([history_entry(
+                1,
+                "254711111111",
+                "Hello",
+                "DeliveredToTerminal",
+                &created_at,
+            )]);
+            let _history = server
+                .mock("POST", "/api/messages")
+                .match_query(Matcher::Any)
+                .with_status(200)
+                .with_body(serde_json::to_string(&history).unwrap())
+                .create();
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let mut request = MessageRequest::new();
+            request.add_message_bag(
+                "254711111111".to_string(),
+                "Hello".to_string(),
+                "UjumbeSMS".to_string(),
+            );
+            let report = UjumbeSmsClient::new(config)
+                .unwrap()
+                .send_messages_with_report(request)
+                .await
+                .unwrap();
+
+            assert_eq!(report.summary().accepted, 1);
+        });
+    }
+}
@@ -0,0 +1,263 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use ujumbe_sms::models::MessageSent;
+use ujumbe_sms::phone::normalize_number;
+use ujumbe_sms::{MessageRequest, UjumbeSmsClient, UjumbeSmsConfig, UjumbeSmsError};
+
+/// Command-line client for the UjumbeSMS API.
+/// Credentials are read from `UJUMBESMS_API_KEY` and `UJUMBESMS_EMAIL` (a `.env` file is loaded if present).
+#[derive(Debug, Parser)]
+#[command(name = "ujumbe", version, about)]
+struct Cli {
+    /// Override the API base URL
+    #[arg(long, env = "UJUMBESMS_BASE_URL", global = true)]
+    base_url: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Send a message, or every message bag in a JSON request file
+    Send(SendArgs),
+    /// Show the credit balance
+    Balance {
+        /// Print the raw JSON response
+        #[arg(long)]
+        json: bool,
+    },
+    /// List sent messages
+    History(HistoryArgs),
+    /// Configuration commands
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Debug, Args)]
+struct SendArgs {
+    /// Comma separated recipient numbers
+    #[arg(
+        long,
+        short = 't',
+        required_unless_present = "file",
+        conflicts_with = "file"
+    )]
+    to: Option<String>,
+    /// Message text
+    #[arg(
+        long,
+        short = 'm',
+        required_unless_present = "file",
+        conflicts_with = "file"
+    )]
+    message: Option<String>,
+    /// Sender ID
+    #[arg(
+        long,
+        short = 's',
+        default_value = "UjumbeSMS",
+        conflicts_with = "file"
+    )]
+    sender: String,
+    /// JSON file in the `MessageRequest` shape: {"data": [{"message_bag": {...}}]}
+    #[arg(long, short = 'f')]
+    file: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct HistoryArgs {
+    /// First page to fetch
+    #[arg(long, default_value_t = 1)]
+    page: u32,
+    /// Number of pages to fetch
+    #[arg(long, default_value_t = 1, conflicts_with = "all")]
+    pages: u32,
+    /// Fetch every page from `--page` to the last
+    #[arg(long)]
+    all: bool,
+    /// Only messages whose status contains this text (case-insensitive)
+    #[arg(long)]
+    status: Option<String>,
+    /// Only messages sent to this number
+    #[arg(long)]
+    number: Option<String>,
+    /// Only messages sent with this sender ID
+    #[arg(long)]
+    sender: Option<String>,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommand {
+    /// Check that credentials are set and accepted by the API
+    Check,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    dotenvy::dotenv().ok();
+    let cli = Cli::parse();
+
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let client = UjumbeSmsClient::new(load_config(cli.base_url.clone())?)?;
+
+    match cli.command {
+        Command::Send(args) => send(&client, args).await,
+        Command::Balance { json } => {
+            let response = client.balance().await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&response)?);
+            } else if let Some(meta) = response.meta {
+                println!("Account: {}", meta.user);
+                println!("Credits: {}", meta.credits);
+                println!("Rate:    {}", meta.rate);
+            } else {
+                println!("{}", response.status.description);
+            }
+            Ok(())
+        }
+        Command::History(args) => history(&client, args).await,
+        Command::Config {
+            command: ConfigCommand::Check,
+        } => {
+            let response = client.balance().await?;
+            println!("UJUMBESMS_API_KEY: set");
+            println!("UJUMBESMS_EMAIL:   set");
+            println!(
+                "API: {} ({})",
+                response.status.description, response.status.code
+            );
+            Ok(())
+        }
+    }
+}
+
+fn load_config(base_url: Option<String>) -> Result<UjumbeSmsConfig, UjumbeSmsError> {
+    let var = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .ok_or_else(|| UjumbeSmsError::InvalidConfig(format!("{name} is not set")))
+    };
+
+    let config = UjumbeSmsConfig::new(var("UJUMBESMS_API_KEY")?, var("UJUMBESMS_EMAIL")?);
+    Ok(match base_url {
+        Some(base_url) => config.with_base_url(base_url),
+        None => config,
+    })
+}
+
+async fn send(client: &UjumbeSmsClient, args: SendArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let request = match args.file {
+        Some(path) => serde_json::from_str::<MessageRequest>(&std::fs::read_to_string(path)?)?,
+        None => {
+            let mut request = MessageRequest::new();
+            request.add_message_bag(
+                args.to.unwrap_or_default(),
+                args.message.unwrap_or_default(),
+                args.sender,
+            );
+            request
+        }
+    };
+
+    let response = client.send_messages(request).await?;
+    println!("{}", response.status.description);
+    if let Some(meta) = response.meta {
+        println!("Recipients:        {}", meta.recipients);
+        println!("Credits deducted:  {}", meta.credits_deducted);
+        println!("Credits available: {}", meta.available_credits);
+    }
+    Ok(())
+}
+
+async fn history(
+    client: &UjumbeSmsClient,
+    args: HistoryArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let number = args.number.as_deref().map(normalize_number);
+    let status = args.status.as_deref().map(str::to_lowercase);
+    let keep = |msg: &MessageSent| {
+        number
+            .as_ref()
+            .is_none_or(|n| normalize_number(&msg.number) == *n)
+            && status
+                .as_ref()
+                .is_none_or(|s| msg.status.to_lowercase().contains(s))
+            && args
+                .sender
+                .as_ref()
+                .is_none_or(|s| msg.sender_id.eq_ignore_ascii_case(s))
+    };
+
+    let mut messages = Vec::new();
+    let mut page = args.page.max(1);
+    let mut remaining = args.pages;
+    loop {
+        let response = client.get_messages_history_page(page).await?;
+        let last_page = response.items.last_page.max(0) as u32;
+        messages.extend(response.items.data.into_iter().filter(|msg| keep(msg)));
+
+        remaining = remaining.saturating_sub(1);
+        if page >= last_page || (!args.all && remaining == 0) {
+            break;
+        }
+        page += 1;
+    }
+
+    match args.format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&messages)?),
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            for message in &messages {
+                writer.serialize(message)?;
+            }
+            writer.flush()?;
+        }
+        OutputFormat::Table => print_table(&messages),
+    }
+    Ok(())
+}
+
+fn print_table(messages: &[MessageSent]) {
+    println!(
+        "{:<10} {:<15} {:<12} {:<22} {:<20} MESSAGE",
+        "ID", "NUMBER", "SENDER", "STATUS", "CREATED"
+    );
+    for msg in messages {
+        let mut text: String = msg.message.chars().take(40).collect();
+        if msg.message.chars().count() > 40 {
+            text.push_str("...");
+        }
+        println!(
+            "{:<10} {:<15} {:<12} {:<22} {:<20} {}",
+            msg.id,
+            msg.number,
+            msg.sender_id,
+            msg.status,
+            msg.created_at,
+            text.replace('\n', " ")
+        );
+    }
+}
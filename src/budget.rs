@@ -0,0 +1,492 @@
+use crate::client::UjumbeSmsClient;
+use crate::errors::UjumbeSmsError;
+use crate::models::{MessageRequest, MessagingApiResponse};
+use crate::sending_window::nairobi_now;
+use chrono::{Datelike, NaiveDate};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// `LowBalanceAlert` is passed to the low-balance callback when credits fall below a threshold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LowBalanceAlert {
+    pub threshold: i64,
+    pub available_credits: i64,
+}
+
+type LowBalanceCallback = Box<dyn Fn(LowBalanceAlert) + Send + Sync>;
+
+#[derive(Debug)]
+struct BudgetState {
+    available_credits: Option<i64>,
+    last_refresh: Option<Instant>,
+    day: NaiveDate,
+    spent_today: i64,
+    spent_this_month: i64,
+    /// Thresholds already alerted on; re-armed once the balance rises above them
+    alerted: Vec<i64>,
+}
+
+impl BudgetState {
+    /// Resets the daily and monthly counters when the `Africa/Nairobi` date rolls over
+    fn roll_over(&mut self, today: NaiveDate) {
+        if today.year() != self.day.year() || today.month() != self.day.month() {
+            self.spent_this_month = 0;
+        }
+        if today != self.day {
+            self.spent_today = 0;
+            self.day = today;
+        }
+    }
+
+    /// Adds `delta` to the spend of a reservation made on `reserved_on`. A day or month that
+    /// has already rolled over keeps its counter, so a late refund cannot lower the new period.
+    fn settle(&mut self, reserved_on: NaiveDate, delta: i64, today: NaiveDate) {
+        self.roll_over(today);
+        if reserved_on == self.day {
+            self.spent_today += delta;
+        }
+        if reserved_on.year() == self.day.year() && reserved_on.month() == self.day.month() {
+            self.spent_this_month += delta;
+        }
+    }
+}
+
+/// `BudgetGuard` wraps `UjumbeSmsClient` and refuses sends whose estimated cost would take the
+/// account below a credit floor or past a daily/monthly spend cap.
+/// Available credits are tracked from every `MessagingMetaInfo` and refreshed with `balance()`
+/// when unknown or older than the refresh interval. Days and months follow `Africa/Nairobi` time.
+pub struct BudgetGuard {
+    client: UjumbeSmsClient,
+    floor: Option<i64>,
+    daily_cap: Option<i64>,
+    monthly_cap: Option<i64>,
+    credits_per_segment: i64,
+    refresh_interval: Option<Duration>,
+    thresholds: Vec<i64>,
+    on_low_balance: Option<LowBalanceCallback>,
+    state: Mutex<BudgetState>,
+}
+
+impl BudgetGuard {
+    pub fn new(client: UjumbeSmsClient) -> Self {
+        BudgetGuard {
+            client,
+            floor: None,
+            daily_cap: None,
+            monthly_cap: None,
+            credits_per_segment: 1,
+            refresh_interval: None,
+            thresholds: Vec::new(),
+            on_low_balance: None,
+            state: Mutex::new(BudgetState {
+                available_credits: None,
+                last_refresh: None,
+                day: nairobi_now().date_naive(),
+                spent_today: 0,
+                spent_this_month: 0,
+                alerted: Vec::new(),
+            }),
+        }
+    }
+
+    /// Minimum credits that must remain after a send
+    pub fn with_floor(mut self, floor: i64) -> Self {
+        self.floor = Some(floor);
+        self
+    }
+
+    pub fn with_daily_cap(mut self, cap: i64) -> Self {
+        self.daily_cap = Some(cap);
+        self
+    }
+
+    pub fn with_monthly_cap(mut self, cap: i64) -> Self {
+        self.monthly_cap = Some(cap);
+        self
+    }
+
+    /// Credits charged per SMS segment per recipient (default: 1)
+    pub fn with_credits_per_segment(mut self, credits: i64) -> Self {
+        self.credits_per_segment = credits;
+        self
+    }
+
+    /// Re-reads the balance before a send when the last reading is older than `interval`
+    pub fn with_refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = Some(interval);
+        self
+    }
+
+    /// Balance levels that trigger the low-balance callback when crossed downwards
+    pub fn with_low_balance_thresholds(mut self, mut thresholds: Vec<i64>) -> Self {
+        thresholds.sort_unstable_by(|a, b| b.cmp(a));
+        self.thresholds = thresholds;
+        self
+    }
+
+    pub fn on_low_balance(
+        mut self,
+        callback: impl Fn(LowBalanceAlert) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_low_balance = Some(Box::new(callback));
+        self
+    }
+
+    pub fn client(&self) -> &UjumbeSmsClient {
+        &self.client
+    }
+
+    /// Last known available credits
+    pub fn available_credits(&self) -> Option<i64> {
+        self.state
+            .lock()
+            .expect("budget lock poisoned")
+            .available_credits
+    }
+
+    /// Credits spent (or reserved by in-flight sends) today and this month
+    pub fn spent(&self) -> (i64, i64) {
+        let mut state = self.state.lock().expect("budget lock poisoned");
+        state.roll_over(nairobi_now().date_naive());
+        (state.spent_today, state.spent_this_month)
+    }
+
+    /// Estimated credits `request` will cost
+    pub fn estimate_cost(&self, request: &MessageRequest) -> i64 {
+        request.estimated_segments() as i64 * self.credits_per_segment
+    }
+
+    /// Fetches the current balance with `UjumbeSmsClient::balance` and updates the guard
+    pub async fn refresh_balance(&self) -> Result<i64, UjumbeSmsError> {
+        let response = self.client.balance().await?;
+        let credits = response
+            .meta
+            .map(|meta| i64::from(meta.credits))
+            .ok_or_else(|| {
+                UjumbeSmsError::ApiError(
+                    response.status.code.clone(),
+                    "Balance response did not include credits".to_string(),
+                )
+            })?;
+
+        let alerts = {
+            let mut state = self.state.lock().expect("budget lock poisoned");
+            state.last_refresh = Some(Instant::now());
+            self.update_available(&mut state, credits)
+        };
+        self.notify(alerts);
+        Ok(credits)
+    }
+
+    /// Sends `request` if its estimated cost fits inside the floor and caps
+    pub async fn send_messages(
+        &self,
+        request: MessageRequest,
+    ) -> Result<MessagingApiResponse, UjumbeSmsError> {
+        if self.needs_refresh() {
+            self.refresh_balance().await?;
+        }
+
+        let cost = self.estimate_cost(&request);
+        let reserved_on = self.reserve(cost)?;
+
+        match self.client.send_messages(request).await {
+            Ok(response) => {
+                let mut alerts = Vec::new();
+                if let Some(meta) = &response.meta {
+                    let mut state = self.state.lock().expect("budget lock poisoned");
+                    let actual = i64::from(meta.credits_deducted);
+                    state.settle(reserved_on, actual - cost, nairobi_now().date_naive());
+                    if let Ok(available) = meta.available_credits.trim().parse::<i64>() {
+                        alerts = self.update_available(&mut state, available);
+                    }
+                }
+                self.notify(alerts);
+                Ok(response)
+            }
+            Err(e) => {
+                let mut state = self.state.lock().expect("budget lock poisoned");
+                state.settle(reserved_on, -cost, nairobi_now().date_naive());
+                if let Some(available) = state.available_credits {
+                    state.available_credits = Some(available + cost);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Convenience method to send a single message through the guard
+    pub async fn send_single_message(
+        &self,
+        numbers: &str,
+        message: &str,
+        sender: &str,
+    ) -> Result<MessagingApiResponse, UjumbeSmsError> {
+        let mut request = MessageRequest::new();
+        request.add_message_bag(numbers.to_string(), message.to_string(), sender.to_string());
+        self.send_messages(request).await
+    }
+
+    fn needs_refresh(&self) -> bool {
+        let state = self.state.lock().expect("budget lock poisoned");
+        match (
+            state.available_credits,
+            state.last_refresh,
+            self.refresh_interval,
+        ) {
+            // Low-balance alerts need a reading even without a floor
+            (None, _, _) => self.floor.is_some() || !self.thresholds.is_empty(),
+            (Some(_), Some(last), Some(interval)) => last.elapsed() >= interval,
+            (Some(_), None, Some(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Checks `cost` against the floor and caps and reserves it for an in-flight send,
+    /// returning the day the reservation counts against
+    fn reserve(&self, cost: i64) -> Result<NaiveDate, UjumbeSmsError> {
+        let mut state = self.state.lock().expect("budget lock poisoned");
+        state.roll_over(nairobi_now().date_naive());
+
+        if let (Some(floor), Some(available)) = (self.floor, state.available_credits) {
+            if available - cost < floor {
+                return Err(UjumbeSmsError::BudgetExceeded(format!(
+                    "sending {cost} credits would leave {} below the floor of {floor}",
+                    available - cost
+                )));
+            }
+        }
+        if let Some(cap) = self.daily_cap {
+            if state.spent_today + cost > cap {
+                return Err(UjumbeSmsError::BudgetExceeded(format!(
+                    "sending {cost} credits would exceed the daily cap of {cap} ({} spent)",
+                    state.spent_today
+                )));
+            }
+        }
+        if let Some(cap) = self.monthly_cap {
+            if state.spent_this_month + cost > cap {
+                return Err(UjumbeSmsError::BudgetExceeded(format!(
+                    "sending {cost} credits would exceed the monthly cap of {cap} ({} spent)",
+                    state.spent_this_month
+                )));
+            }
+        }
+
+        state.spent_today += cost;
+        state.spent_this_month += cost;
+        if let Some(available) = state.available_credits {
+            state.available_credits = Some(available - cost);
+        }
+        Ok(state.day)
+    }
+
+    /// Records a new balance reading and returns an alert for each threshold crossed downwards
+    fn update_available(&self, state: &mut BudgetState, available: i64) -> Vec<LowBalanceAlert> {
+        state.available_credits = Some(available);
+        state.alerted.retain(|threshold| available < *threshold);
+
+        let mut alerts = Vec::new();
+        for &threshold in &self.thresholds {
+            if available < threshold && !state.alerted.contains(&threshold) {
+                state.alerted.push(threshold);
+                alerts.push(LowBalanceAlert {
+                    threshold,
+                    available_credits: available,
+                });
+            }
+        }
+        alerts
+    }
+
+    /// Runs the low-balance callback; called without the state lock so it may use the guard
+    fn notify(&self, alerts: Vec<LowBalanceAlert>) {
+        if let Some(callback) = &self.on_low_balance {
+            alerts.into_iter().for_each(callback);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{balance_body, messaging_body_with_credits};
+    use crate::UjumbeSmsConfig;
+    use mockito::Server;
+    use std::sync::{Arc, OnceLock, Weak};
+
+    #[test]
+    fn test_floor_rejects_send_and_tracks_balance() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let balance = server
+                .mock("POST", "/api/balance")
+                .with_status(200)
+                .with_body(balance_body(12))
+                .expect(1)
+                .create();
+            let messaging = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_body(messaging_body_with_credits(2, 2, 10))
+                .expect(1)
+                .create();
+
+            let alerts = Arc::new(Mutex::new(Vec::new()));
+            let recorded = alerts.clone();
+            // The callback reads the guard it belongs to, which must not deadlock
+            let this: Arc<OnceLock<Weak<BudgetGuard>>> = Arc::default();
+            let callback_guard = this.clone();
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let guard = BudgetGuard::new(UjumbeSmsClient::new(config).unwrap())
+                .with_floor(9)
+                .with_low_balance_thresholds(vec![11, 50])
+                .on_low_balance(move |alert| {
+                    let guard = callback_guard.get().and_then(Weak::upgrade).unwrap();
+                    assert_eq!(guard.available_credits(), Some(alert.available_credits));
+                    recorded.lock().unwrap().push(alert);
+                });
+            let guard = Arc::new(guard);
+            this.set(Arc::downgrade(&guard)).unwrap();
+
+            guard
+                .send_single_message("254711111111,254722222222", "Hello", "UjumbeSMS")
+                .await
+                .unwrap();
+            assert_eq!(guard.available_credits(), Some(10));
+            assert_eq!(guard.spent(), (2, 2));
+
+            let result = guard
+                .send_single_message("254711111111,254722222222", "Hello", "UjumbeSMS")
+                .await;
+            assert!(matches!(result, Err(UjumbeSmsError::BudgetExceeded(_))));
+
+            balance.assert();
+            messaging.assert();
+            assert_eq!(
+                *alerts.lock().unwrap(),
+                vec![
+                    LowBalanceAlert {
+                        threshold: 50,
+                        available_credits: 12
+                    },
+                    LowBalanceAlert {
+                        threshold: 11,
+                        available_credits: 10
+                    },
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_thresholds_alone_fetch_the_balance() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let balance = server
+                .mock("POST", "/api/balance")
+                .with_status(200)
+                .with_body(balance_body(40))
+                .expect(1)
+                .create();
+            let _messaging = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_body(messaging_body_with_credits(1, 1, 39))
+                .create();
+
+            let alerts = Arc::new(Mutex::new(Vec::new()));
+            let recorded = alerts.clone();
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let guard = BudgetGuard::new(UjumbeSmsClient::new(config).unwrap())
+                .with_low_balance_thresholds(vec![50])
+                .on_low_balance(move |alert| recorded.lock().unwrap().push(alert));
+
+            guard
+                .send_single_message("254711111111", "Hello", "UjumbeSMS")
+                .await
+                .unwrap();
+            balance.assert();
+            assert_eq!(
+                *alerts.lock().unwrap(),
+                vec![LowBalanceAlert {
+                    threshold: 50,
+                    available_credits: 40
+                }]
+            );
+        });
+    }
+
+    #[test]
+    fn test_daily_cap_counts_segments() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let messaging = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_body(messaging_body_with_credits(3, 3, 100))
+                .expect(0)
+                .create();
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let guard = BudgetGuard::new(UjumbeSmsClient::new(config).unwrap()).with_daily_cap(5);
+
+            // 200 GSM characters need two segments, so three recipients cost six credits
+            let long_message = "a".repeat(200);
+            let result = guard
+                .send_single_message(
+                    "254711111111,254722222222,254733333333",
+                    &long_message,
+                    "UjumbeSMS",
+                )
+                .await;
+
+            assert!(matches!(result, Err(UjumbeSmsError::BudgetExceeded(_))));
+            assert_eq!(guard.spent(), (0, 0));
+            messaging.assert();
+        });
+    }
+
+    #[test]
+    fn test_settling_after_rollover_leaves_new_period_alone() {
+        let date = |month, day| NaiveDate::from_ymd_opt(2025, month, day).unwrap();
+        let mut state = BudgetState {
+            available_credits: None,
+            last_refresh: None,
+            day: date(1, 31),
+            spent_today: 5,
+            spent_this_month: 20,
+            alerted: Vec::new(),
+        };
+
+        // A send reserved on 31 January fails after midnight on 1 February
+        state.settle(date(1, 31), -5, date(2, 1));
+        assert_eq!((state.spent_today, state.spent_this_month), (0, 0));
+
+        // Reserved on 1 February, settled on the 2nd: only the month is adjusted
+        state.spent_today = 4;
+        state.spent_this_month = 4;
+        state.settle(date(2, 1), -4, date(2, 2));
+        assert_eq!((state.spent_today, state.spent_this_month), (0, 0));
+
+        state.spent_today = 3;
+        state.spent_this_month = 3;
+        state.settle(date(2, 2), -1, date(2, 2));
+        assert_eq!((state.spent_today, state.spent_this_month), (2, 2));
+    }
+}
@@ -0,0 +1,357 @@
+use crate::client::UjumbeSmsClient;
+use crate::errors::UjumbeSmsError;
+use crate::models::{MessageBag, MessageBagContainer, MessageRequest, MessagingApiResponse};
+use crate::rate_limit::RateLimiter;
+use futures_util::{future, Stream, StreamExt};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Default number of message bags sent in one `MessageRequest`
+pub const DEFAULT_BAGS_PER_REQUEST: usize = 100;
+/// Default number of requests in flight at once
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// `BulkProgress` is published after every request a `BulkSender` completes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BulkProgress {
+    pub requests_sent: u64,
+    pub requests_failed: u64,
+    pub bags_sent: u64,
+    pub bags_failed: u64,
+    pub recipients_sent: u64,
+    pub recipients_failed: u64,
+    /// Sum of `credits_deducted` over successful requests
+    pub credits_used: u64,
+    pub elapsed: Duration,
+    /// Estimated time left, only known when the total was given with `with_total_bags`
+    pub eta: Option<Duration>,
+}
+
+/// `BulkFailure` is a request the API rejected, kept so it can be retried
+#[derive(Debug)]
+pub struct BulkFailure {
+    pub request: MessageRequest,
+    pub error: UjumbeSmsError,
+}
+
+/// `BulkReport` is the final state of a bulk send
+#[derive(Debug, Default)]
+pub struct BulkReport {
+    pub progress: BulkProgress,
+    pub failures: Vec<BulkFailure>,
+    /// The send was cancelled before the stream ran out
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BulkState {
+    Running,
+    Paused,
+    Cancelled,
+}
+
+/// `BulkSender` sends a stream of message bags, batched into `MessageRequest`s, with bounded
+/// concurrency and an optional rate limit. Use `futures_util::stream::iter` to send a `Vec` or
+/// any other iterator.
+pub struct BulkSender {
+    client: UjumbeSmsClient,
+    bags_per_request: usize,
+    concurrency: usize,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    total_bags: Option<u64>,
+}
+
+impl BulkSender {
+    pub fn new(client: UjumbeSmsClient) -> Self {
+        BulkSender {
+            client,
+            bags_per_request: DEFAULT_BAGS_PER_REQUEST,
+            concurrency: DEFAULT_CONCURRENCY,
+            rate_limiter: None,
+            total_bags: None,
+        }
+    }
+
+    pub fn with_bags_per_request(mut self, bags_per_request: usize) -> Self {
+        self.bags_per_request = bags_per_request.max(1);
+        self
+    }
+
+    /// Maximum number of requests in flight at once
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Starts at most `requests` requests in any `per` period
+    pub fn with_rate_limit(mut self, requests: u32, per: Duration) -> Self {
+        self.rate_limiter = Some(Arc::new(RateLimiter::new(requests, per)));
+        self
+    }
+
+    /// Total number of bags the stream will yield, used to estimate the ETA
+    pub fn with_total_bags(mut self, total_bags: u64) -> Self {
+        self.total_bags = Some(total_bags);
+        self
+    }
+
+    /// Starts sending on the current Tokio runtime
+    pub fn spawn<S>(self, bags: S) -> BulkSendHandle
+    where
+        S: Stream<Item = MessageBag> + Send + 'static,
+    {
+        let (control_tx, control_rx) = watch::channel(BulkState::Running);
+        let (progress_tx, progress_rx) = watch::channel(BulkProgress::default());
+        let task = tokio::spawn(run(self, bags, control_rx, progress_tx));
+
+        BulkSendHandle {
+            progress: progress_rx,
+            control: control_tx,
+            task,
+        }
+    }
+}
+
+async fn run<S>(
+    sender: BulkSender,
+    bags: S,
+    control: watch::Receiver<BulkState>,
+    progress_tx: watch::Sender<BulkProgress>,
+) -> BulkReport
+where
+    S: Stream<Item = MessageBag> + Send + 'static,
+{
+    let started = Instant::now();
+    let mut report = BulkReport::default();
+
+    let stop = control.clone();
+    let requests = bags
+        // Stop pulling from the stream once cancelled
+        .take_while(move |_| future::ready(!is_cancelled(&stop)))
+        .chunks(sender.bags_per_request)
+        .map(|bags| MessageRequest {
+            data: bags
+                .into_iter()
+                .map(|message_bag| MessageBagContainer { message_bag })
+                .collect(),
+        })
+        .map(|request| {
+            send_request(
+                sender.client.clone(),
+                sender.rate_limiter.clone(),
+                control.clone(),
+                request,
+            )
+        })
+        .buffer_unordered(sender.concurrency);
+    let mut requests = std::pin::pin!(requests);
+
+    while let Some(outcome) = requests.next().await {
+        let Some((request, result)) = outcome else {
+            continue;
+        };
+        let progress = &mut report.progress;
+        let bags = request.data.len() as u64;
+        let recipients = request.recipient_count() as u64;
+        match result {
+            Ok(response) => {
+                progress.requests_sent += 1;
+                progress.bags_sent += bags;
+                progress.recipients_sent += recipients;
+                progress.credits_used += response
+                    .meta
+                    .map_or(0, |meta| meta.credits_deducted.max(0) as u64);
+            }
+            Err(error) => {
+                progress.requests_failed += 1;
+                progress.bags_failed += bags;
+                progress.recipients_failed += recipients;
+                report.failures.push(BulkFailure { request, error });
+            }
+        }
+
+        progress.elapsed = started.elapsed();
+        let done = progress.bags_sent + progress.bags_failed;
+        progress.eta = sender.total_bags.map(|total| {
+            let remaining = total.saturating_sub(done);
+            progress.elapsed.mul_f64(remaining as f64 / done as f64)
+        });
+        progress_tx.send_replace(*progress);
+    }
+
+    report.progress.elapsed = started.elapsed();
+    report.cancelled = is_cancelled(&control);
+    report
+}
+
+/// Cancelled explicitly, or the handle was dropped
+fn is_cancelled(control: &watch::Receiver<BulkState>) -> bool {
+    control.has_changed().is_err() || *control.borrow() == BulkState::Cancelled
+}
+
+/// Waits out a pause and the rate limit, then sends `request`; `None` if cancelled first
+async fn send_request(
+    client: UjumbeSmsClient,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    mut control: watch::Receiver<BulkState>,
+    request: MessageRequest,
+) -> Option<(MessageRequest, Result<MessagingApiResponse, UjumbeSmsError>)> {
+    let _ = control.wait_for(|state| *state != BulkState::Paused).await;
+    if is_cancelled(&control) {
+        return None;
+    }
+    if let Some(rate_limiter) = rate_limiter {
+        rate_limiter.acquire().await;
+    }
+    let result = client.send_messages(request.clone()).await;
+    Some((request, result))
+}
+
+/// `BulkSendHandle` controls a running `BulkSender`. Dropping it cancels the send.
+pub struct BulkSendHandle {
+    progress: watch::Receiver<BulkProgress>,
+    control: watch::Sender<BulkState>,
+    task: JoinHandle<BulkReport>,
+}
+
+impl BulkSendHandle {
+    /// Watch channel holding the latest `BulkProgress`
+    pub fn progress(&self) -> watch::Receiver<BulkProgress> {
+        self.progress.clone()
+    }
+
+    /// Stops starting new requests; requests already in flight complete
+    pub fn pause(&self) {
+        self.control.send_if_modified(|state| {
+            let pause = *state == BulkState::Running;
+            if pause {
+                *state = BulkState::Paused;
+            }
+            pause
+        });
+    }
+
+    pub fn resume(&self) {
+        self.control.send_if_modified(|state| {
+            let resume = *state == BulkState::Paused;
+            if resume {
+                *state = BulkState::Running;
+            }
+            resume
+        });
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.control.borrow() == BulkState::Paused
+    }
+
+    /// Stops sending. Requests in flight complete; bags not yet sent are discarded.
+    pub fn cancel(&self) {
+        self.control.send_replace(BulkState::Cancelled);
+    }
+
+    /// Waits for the send to finish and returns the report. A paused send must be resumed
+    /// or cancelled first. Fails with `UjumbeSmsError::TaskStopped` if the send task panicked
+    /// or its runtime shut down.
+    pub async fn finish(self) -> Result<BulkReport, UjumbeSmsError> {
+        let BulkSendHandle { task, control, .. } = self;
+        let report = task
+            .await
+            .map_err(|error| UjumbeSmsError::TaskStopped(format!("bulk send task: {error}")))?;
+        drop(control);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::messaging_body;
+    use crate::UjumbeSmsConfig;
+    use mockito::{Matcher, Server};
+
+    fn bag(number: &str, message: &str) -> MessageBag {
+        MessageBag {
+            numbers: number.to_string(),
+            message: message.to_string(),
+            sender: "UjumbeSMS".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_bulk_send_with_pause_and_cancel() {
+        let mut server = Server::new();
+        let url = server.url();
+        // A single-threaded runtime only runs the send task when the test yields,
+        // so pausing and cancelling before the first request is deterministic
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let sent = server
+                .mock("POST", "/api/messaging")
+                .match_body(Matcher::Regex(r#""message":"Hello""#.to_string()))
+                .with_status(200)
+                .with_body(messaging_body(2))
+                .expect(2)
+                .create();
+            let rejected = server
+                .mock("POST", "/api/messaging")
+                .match_body(Matcher::Regex(r#""message":"Fail""#.to_string()))
+                .with_status(500)
+                .with_body("Internal Server Error")
+                .create();
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let client = UjumbeSmsClient::new(config).unwrap();
+            let bags = vec![
+                bag("254711111111", "Hello"),
+                bag("254722222222", "Hello"),
+                bag("254733333333", "Hello"),
+                bag("254744444444", "Hello"),
+                bag("254755555555,254766666666", "Fail"),
+            ];
+
+            let handle = BulkSender::new(client.clone())
+                .with_bags_per_request(2)
+                .with_concurrency(2)
+                .with_rate_limit(100, Duration::from_secs(1))
+                .with_total_bags(5)
+                .spawn(futures_util::stream::iter(bags.clone()));
+            handle.pause();
+            assert!(handle.is_paused());
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(*handle.progress().borrow(), BulkProgress::default());
+
+            handle.resume();
+            let report = handle.finish().await.unwrap();
+            sent.assert();
+            rejected.assert();
+
+            assert!(!report.cancelled);
+            assert_eq!(report.progress.requests_sent, 2);
+            assert_eq!(report.progress.requests_failed, 1);
+            assert_eq!(report.progress.bags_sent, 4);
+            assert_eq!(report.progress.recipients_failed, 2);
+            assert_eq!(report.progress.credits_used, 4);
+            assert_eq!(report.progress.eta, Some(Duration::ZERO));
+            assert_eq!(report.failures.len(), 1);
+            assert_eq!(
+                report.failures[0].request.data[0].message_bag.message,
+                "Fail"
+            );
+
+            let handle = BulkSender::new(client).spawn(futures_util::stream::iter(bags));
+            handle.cancel();
+            let report = handle.finish().await.unwrap();
+            assert!(report.cancelled);
+            assert_eq!(report.progress.requests_sent, 0);
+        });
+    }
+}
@@ -0,0 +1,406 @@
+use crate::errors::UjumbeSmsError;
+use crate::transport::{BoxError, HttpRequest, HttpResponse, HttpService};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tower::Service;
+
+/// Placeholder written in place of credentials in recorded cassettes
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Headers whose values are never written to a cassette
+const SENSITIVE_HEADERS: [&str; 2] = ["x-authorization", "email"];
+
+/// `CassetteMode` selects whether `UjumbeSmsClient` records HTTP exchanges or replays them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Perform real requests and write every exchange to the cassette file
+    Record(PathBuf),
+    /// Serve responses from the cassette file without touching the network
+    Replay(PathBuf),
+}
+
+/// `RecordedRequest` is the request half of an interaction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Endpoint path including any query string, e.g. `/api/messages?page=2`
+    pub path: String,
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<serde_json::Value>,
+}
+
+/// `RecordedResponse` is the response half of an interaction
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+/// `CassetteFile` is the on-disk JSON format of a cassette
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CassetteFile {
+    pub interactions: Vec<Interaction>,
+}
+
+impl CassetteFile {
+    pub fn load(path: &Path) -> Result<Self, UjumbeSmsError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            UjumbeSmsError::CassetteError(format!("cannot read {}: {e}", path.display()))
+        })?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), UjumbeSmsError> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?).map_err(|e| {
+            UjumbeSmsError::CassetteError(format!("cannot write {}: {e}", path.display()))
+        })
+    }
+}
+
+#[derive(Debug)]
+struct CassetteState {
+    file: CassetteFile,
+    /// Replay only: whether each interaction has already been served
+    used: Vec<bool>,
+}
+
+/// `Cassette` records or replays the HTTP exchanges of one client
+#[derive(Debug)]
+pub(crate) struct Cassette {
+    mode: CassetteMode,
+    /// The account email, scrubbed from recorded bodies
+    email: String,
+    state: Mutex<CassetteState>,
+}
+
+impl Cassette {
+    /// Opens the cassette for `mode`. Recording starts a fresh cassette; replaying loads it.
+    pub(crate) fn open(mode: CassetteMode, email: &str) -> Result<Self, UjumbeSmsError> {
+        let file = match &mode {
+            CassetteMode::Record(_) => CassetteFile::default(),
+            CassetteMode::Replay(path) => CassetteFile::load(path)?,
+        };
+        let used = vec![false; file.interactions.len()];
+
+        Ok(Cassette {
+            mode,
+            email: email.to_string(),
+            state: Mutex::new(CassetteState { file, used }),
+        })
+    }
+
+    pub(crate) fn is_replay(&self) -> bool {
+        matches!(self.mode, CassetteMode::Replay(_))
+    }
+
+    /// Builds the redacted form of a request, used both for recording and for matching
+    pub(crate) fn request(&self, request: &HttpRequest) -> RecordedRequest {
+        let headers = request
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                    REDACTED.to_string()
+                } else {
+                    value.to_str().unwrap_or_default().to_string()
+                };
+                (name.as_str().to_string(), value)
+            })
+            .collect();
+
+        RecordedRequest {
+            method: request.method.to_string(),
+            path: request.path.clone(),
+            headers,
+            body: request
+                .body
+                .as_deref()
+                .and_then(|b| serde_json::from_slice(b).ok()),
+        }
+    }
+
+    /// Serves the first unused recorded interaction matching `request` by method, path and body
+    pub(crate) fn replay(&self, request: &RecordedRequest) -> Result<HttpResponse, UjumbeSmsError> {
+        let mut state = self.state.lock().expect("cassette lock poisoned");
+        let CassetteState { file, used } = &mut *state;
+
+        let index = file
+            .interactions
+            .iter()
+            .enumerate()
+            .position(|(i, interaction)| {
+                !used[i]
+                    && interaction.request.method == request.method
+                    && interaction.request.path == request.path
+                    && interaction.request.body == request.body
+            })
+            .ok_or_else(|| {
+                UjumbeSmsError::CassetteError(format!(
+                    "no unused recorded interaction matches {} {} with body {}",
+                    request.method,
+                    request.path,
+                    request
+                        .body
+                        .as_ref()
+                        .map_or_else(|| "<empty>".to_string(), |b| b.to_string())
+                ))
+            })?;
+
+        used[index] = true;
+        let response = &file.interactions[index].response;
+        let status = StatusCode::from_u16(response.status)
+            .map_err(|e| UjumbeSmsError::CassetteError(format!("invalid recorded status: {e}")))?;
+        Ok(HttpResponse {
+            status,
+            body: response.body.clone(),
+        })
+    }
+
+    /// Appends an exchange and rewrites the cassette file. The exchange is kept even if the write
+    /// fails, so the next successful write includes it.
+    pub(crate) fn record(
+        &self,
+        request: RecordedRequest,
+        status: StatusCode,
+        body: &str,
+    ) -> Result<(), UjumbeSmsError> {
+        let CassetteMode::Record(path) = &self.mode else {
+            return Ok(());
+        };
+
+        let body = if self.email.is_empty() {
+            body.to_string()
+        } else {
+            body.replace(&self.email, REDACTED)
+        };
+
+        let mut state = self.state.lock().expect("cassette lock poisoned");
+        state.file.interactions.push(Interaction {
+            request,
+            response: RecordedResponse {
+                status: status.as_u16(),
+                body,
+            },
+        });
+        state.file.save(path)
+    }
+}
+
+/// `CassetteService` sits directly above the transport, recording or replaying every exchange
+#[derive(Clone)]
+pub(crate) struct CassetteService {
+    cassette: Arc<Cassette>,
+    inner: HttpService,
+}
+
+impl CassetteService {
+    pub(crate) fn new(cassette: Cassette, inner: HttpService) -> Self {
+        CassetteService {
+            cassette: Arc::new(cassette),
+            inner,
+        }
+    }
+}
+
+impl Service<HttpRequest> for CassetteService {
+    type Response = HttpResponse;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<HttpResponse, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.cassette.is_replay() {
+            Poll::Ready(Ok(()))
+        } else {
+            self.inner.poll_ready(cx)
+        }
+    }
+
+    fn call(&mut self, request: HttpRequest) -> Self::Future {
+        let cassette = self.cassette.clone();
+        let recorded = cassette.request(&request);
+
+        if cassette.is_replay() {
+            request.begin_attempt();
+            return Box::pin(async move { Ok(cassette.replay(&recorded)?) });
+        }
+
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let response = response.await?;
+            // The request has already been sent, so failing it now would invite a retry that
+            // sends it twice
+            if let Err(error) = cassette.record(recorded, response.status, &response.body) {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(error = %error, "UjumbeSMS cassette write failed");
+                #[cfg(not(feature = "tracing"))]
+                eprintln!("UjumbeSMS cassette write failed: {error}");
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::balance_body;
+    use crate::{MessageRequest, UjumbeSmsClient, UjumbeSmsConfig};
+    use mockito::Server;
+
+    fn cassette_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ujumbe_{name}_{}.json", std::process::id()))
+    }
+
+    #[test]
+    fn test_record_then_replay() {
+        let mut server = Server::new();
+        let url = server.url();
+        let path = cassette_path("record_then_replay");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let _mock = server
+                .mock("POST", "/api/balance")
+                .with_status(200)
+                .with_header("content-type", "application/json")
+                .with_body(balance_body(10))
+                .expect(1)
+                .create();
+
+            let recording =
+                UjumbeSmsConfig::new("secret_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let client = UjumbeSmsClient::builder(recording)
+                .with_cassette(CassetteMode::Record(path.clone()))
+                .build()
+                .unwrap();
+            client.balance().await.unwrap();
+            _mock.assert();
+
+            let recorded = std::fs::read_to_string(&path).unwrap();
+            assert!(!recorded.contains("secret_api_key"));
+            assert!(!recorded.contains("test@email.com"));
+            assert!(recorded.contains(REDACTED));
+
+            // Replay never reaches the network, so any base URL works
+            let replaying =
+                UjumbeSmsConfig::new("other_key".to_string(), "other@email.com".to_string())
+                    .with_base_url("http://127.0.0.1:9".to_string());
+            let client = UjumbeSmsClient::builder(replaying)
+                .with_cassette(CassetteMode::Replay(path.clone()))
+                .build()
+                .unwrap();
+
+            let response = client.balance().await.unwrap();
+            assert_eq!(response.meta.unwrap().credits, 10);
+
+            // The single recorded balance call has been used up
+            assert!(matches!(
+                client.balance().await,
+                Err(UjumbeSmsError::CassetteError(_))
+            ));
+        });
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_record_write_failure_returns_response() {
+        let mut server = Server::new();
+        let url = server.url();
+        let path = cassette_path("missing_dir").join("cassette.json");
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let mock = server
+                .mock("POST", "/api/balance")
+                .with_status(200)
+                .with_body(balance_body(10))
+                .expect(1)
+                .create();
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let client = UjumbeSmsClient::builder(config)
+                .with_cassette(CassetteMode::Record(path.clone()))
+                .build()
+                .unwrap();
+
+            let response = client.balance().await.unwrap();
+            assert_eq!(response.meta.unwrap().credits, 10);
+            mock.assert();
+            assert!(!path.exists());
+        });
+    }
+
+    #[test]
+    fn test_replay_rejects_unrecorded_request() {
+        let path = cassette_path("unrecorded");
+        CassetteFile {
+            interactions: vec![Interaction {
+                request: RecordedRequest {
+                    method: "POST".to_string(),
+                    path: "/api/messaging".to_string(),
+                    headers: BTreeMap::new(),
+                    body: Some(serde_json::json!({
+                        "data": [{ "message_bag": {
+                            "numbers": "254712345678", "message": "Recorded", "sender": "UjumbeSMS"
+                        }}]
+                    })),
+                },
+                response: RecordedResponse {
+                    status: 400,
+                    body:
+                        r#"{"status": {"code": "1001", "type": "error", "description": "Invalid"}}"#
+                            .to_string(),
+                },
+            }],
+        }
+        .save(&path)
+        .unwrap();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string());
+            let client = UjumbeSmsClient::builder(config)
+                .with_cassette(CassetteMode::Replay(path.clone()))
+                .build()
+                .unwrap();
+
+            let mut request = MessageRequest::new();
+            request.add_message_bag(
+                "254712345678".to_string(),
+                "Different".to_string(),
+                "UjumbeSMS".to_string(),
+            );
+            match client.send_messages(request).await {
+                Err(UjumbeSmsError::CassetteError(msg)) => assert!(msg.contains("/api/messaging")),
+                other => panic!("Expected CassetteError, got {other:?}"),
+            }
+
+            // Recorded error responses are replayed as API errors
+            let result = client
+                .send_single_message("254712345678", "Recorded", "UjumbeSMS")
+                .await;
+            assert!(
+                matches!(result, Err(UjumbeSmsError::ApiError(code, _)) if code == "400 Bad Request")
+            );
+        });
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,29 +1,49 @@
+use crate::batch::{BatchSendReport, DEFAULT_HISTORY_PAGES};
 use crate::config::UjumbeSmsConfig;
 use crate::errors::UjumbeSmsError;
+use crate::hooks::{HookDecision, RequestHook};
 use crate::models::{
     BalanceApiResponse, MessageHistoryApiResponse, MessageRequest, MessagingApiResponse,
 };
+use crate::sender::SenderRegistry;
+use crate::telemetry::{report_sender_substitutions, ApiResponse, CallTelemetry};
+use crate::transport::{
+    into_client_error, BoxError, HttpRequest, HttpResponse, HttpService, ReqwestService,
+};
 use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
-use reqwest::Client as ReqwestClient;
+use reqwest::{Client as ReqwestClient, Method, StatusCode};
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+use tower::util::BoxCloneSyncService;
+use tower::{Layer, Service, ServiceExt};
 
 /// UjumbeSMS Rust client for sending messages using the UjumbeSMS API
 /// Crate: https://crates.io/crates/ujumbe_sms
+#[derive(Clone)]
 pub struct UjumbeSmsClient {
     config: UjumbeSmsConfig,
-    http_client: ReqwestClient,
+    service: HttpService,
+    hooks: Arc<[Arc<dyn RequestHook>]>,
+    sender_registry: Option<Arc<SenderRegistry>>,
 }
 
 impl UjumbeSmsClient {
     /// Creates a new UjumbeSMS client with the given configuration
     pub fn new(config: UjumbeSmsConfig) -> Result<Self, UjumbeSmsError> {
-        let http_client = ReqwestClient::builder()
-            .build()
-            .map_err(UjumbeSmsError::from)?;
+        Self::builder(config).build()
+    }
 
-        Ok(UjumbeSmsClient {
+    /// Starts building a client whose HTTP layer can be wrapped in custom `tower` layers
+    pub fn builder(config: UjumbeSmsConfig) -> UjumbeSmsClientBuilder {
+        UjumbeSmsClientBuilder {
             config,
-            http_client,
-        })
+            http_client: None,
+            layers: Vec::new(),
+            hooks: Vec::new(),
+            sender_registry: None,
+            #[cfg(feature = "cassette")]
+            cassette: None,
+        }
     }
 
     /// Internal method to attach headers with `UjumbeSmsConfig` configurations set
@@ -45,38 +65,105 @@ impl UjumbeSmsClient {
         Ok(headers)
     }
 
-    /// Sends messages using the UjumbeSMS API: https://ujumbesms.co.ke/api/messaging
-    pub async fn send_messages(
+    /// Internal method to POST to `path` (an endpoint path plus optional query string)
+    /// through the service stack and return the response with the number of attempts it took
+    async fn execute(
         &self,
-        request: MessageRequest,
-    ) -> Result<MessagingApiResponse, UjumbeSmsError> {
-        let url = format!(
-            "{}{}",
-            self.config.base_url,
-            ApiEndpoint::Messaging.as_str()
+        path: &str,
+        body: Option<Vec<u8>>,
+    ) -> Result<(HttpResponse, u32), UjumbeSmsError> {
+        let request = HttpRequest::new(
+            Method::POST,
+            format!("{}{}", self.config.base_url, path),
+            path.to_string(),
+            self.attach_headers()?,
+            body,
         );
-
-        let headers = self.attach_headers()?;
+        let attempts = request.clone();
 
         let response = self
-            .http_client
-            .post(&url)
-            .headers(headers)
-            .json(&request)
-            .send()
-            .await?;
-
-        if response.status().is_success() {
-            let api_response = response.json::<MessagingApiResponse>().await?;
-            Ok(api_response)
+            .service
+            .clone()
+            .oneshot(request)
+            .await
+            .map_err(into_client_error)?;
+        Ok((response, attempts.attempts()))
+    }
+
+    /// Internal method to turn a raw response into `T`, or an `ApiError` for non-success statuses.
+    /// The body arrives as text from the service stack, so a malformed body is a `SerializationError`.
+    fn parse_response<T: DeserializeOwned>(
+        status: StatusCode,
+        text: String,
+    ) -> Result<T, UjumbeSmsError> {
+        if status.is_success() {
+            Ok(serde_json::from_str(&text)?)
         } else {
-            // Store the status before consuming the response with text()
-            let status = response.status().to_string();
-            let error_text = response.text().await?;
-            Err(UjumbeSmsError::ApiError(status, error_text))
+            Err(UjumbeSmsError::ApiError(status.to_string(), text))
         }
     }
 
+    /// Internal method wrapping a full API call (serialize, execute, parse) in telemetry
+    async fn call<T: DeserializeOwned + ApiResponse>(
+        &self,
+        endpoint: ApiEndpoint,
+        path: &str,
+        request: Option<&MessageRequest>,
+    ) -> Result<T, UjumbeSmsError> {
+        let telemetry = CallTelemetry::start(&endpoint, request);
+        let body = match request.map(serde_json::to_string).transpose() {
+            Ok(body) => body,
+            Err(error) => {
+                let result = Err(error.into());
+                telemetry.finish(&result);
+                return result;
+            }
+        };
+
+        let result = telemetry
+            .instrument(async {
+                for hook in self.hooks.iter() {
+                    if let HookDecision::Veto(reason) =
+                        hook.before_request(&endpoint, body.as_deref())
+                    {
+                        return Err(UjumbeSmsError::Vetoed(reason));
+                    }
+                }
+                let (response, attempts) = self
+                    .execute(path, body.clone().map(String::into_bytes))
+                    .await?;
+                telemetry.http_response(response.status, attempts);
+                Self::parse_response(response.status, response.body)
+            })
+            .await;
+        telemetry.finish(&result);
+
+        for hook in self.hooks.iter() {
+            let outcome = result.as_ref().map(T::hook_response);
+            hook.after_response(&endpoint, body.as_deref(), outcome);
+        }
+        result
+    }
+
+    /// Sends messages using the UjumbeSMS API: https://ujumbesms.co.ke/api/messaging
+    /// With a sender registry configured, unapproved senders are first replaced by its default
+    /// sender; each replacement is reported as a tracing event and a metric (see `telemetry`)
+    pub async fn send_messages(
+        &self,
+        mut request: MessageRequest,
+    ) -> Result<MessagingApiResponse, UjumbeSmsError> {
+        if let Some(registry) = &self.sender_registry {
+            let substitutions = request.resolve_senders(registry)?;
+            report_sender_substitutions(&substitutions);
+        }
+        self.call(
+            ApiEndpoint::Messaging,
+            ApiEndpoint::Messaging.as_str(),
+            Some(&request),
+        )
+        .await
+    }
+
     /// Convenience method to send a single message to multiple recipients
     pub async fn send_single_message(
         &self,
@@ -89,40 +176,130 @@ impl UjumbeSmsClient {
         self.send_messages(request).await
     }
 
+    /// Sends `request` and reports the outcome of every bag and number. Blank, malformed and
+    /// repeated numbers are dropped before sending; the rest are matched against the message history.
+    /// A failed history lookup leaves recipients `Pending` and is kept in `reconcile_error`,
+    /// see `BatchSendReport::reconcile`.
+    pub async fn send_messages_with_report(
+        &self,
+        request: MessageRequest,
+    ) -> Result<BatchSendReport, UjumbeSmsError> {
+        let (mut report, sendable) = BatchSendReport::prepare(request);
+        if sendable.data.is_empty() {
+            return Ok(report);
+        }
+
+        let response = self.send_messages(sendable).await?;
+        report.sent_at = Some(BatchSendReport::send_time(&response));
+        report.response = Some(response);
+        report.reconcile_error = report.reconcile(self, DEFAULT_HISTORY_PAGES).await.err();
+        Ok(report)
+    }
+
     /// Credit balance inquiry: https://ujumbesms.co.ke/api/balance
     pub async fn balance(&self) -> Result<BalanceApiResponse, UjumbeSmsError> {
-        let url = format!("{}{}", self.config.base_url, ApiEndpoint::Balances.as_str());
+        self.call(ApiEndpoint::Balances, ApiEndpoint::Balances.as_str(), None)
+            .await
+    }
+
+    /// Get messages history: https://ujumbesms.co.ke/api/messages
+    pub async fn get_messages_history(&self) -> Result<MessageHistoryApiResponse, UjumbeSmsError> {
+        self.call(ApiEndpoint::Messages, ApiEndpoint::Messages.as_str(), None)
+            .await
+    }
 
-        let headers = self.attach_headers()?;
+    /// Get a specific page of the messages history (pages start at 1)
+    pub async fn get_messages_history_page(
+        &self,
+        page: u32,
+    ) -> Result<MessageHistoryApiResponse, UjumbeSmsError> {
+        let path = format!("{}?page={page}", ApiEndpoint::Messages.as_str());
+        self.call(ApiEndpoint::Messages, &path, None).await
+    }
+}
 
-        let response = self.http_client.post(&url).headers(headers).send().await?;
+type LayerFn = Box<dyn FnOnce(HttpService) -> HttpService + Send>;
 
-        if response.status().is_success() {
-            let api_response = response.json::<BalanceApiResponse>().await?;
-            Ok(api_response)
-        } else {
-            let status = response.status().to_string();
-            let error_text = response.text().await?;
-            Err(UjumbeSmsError::ApiError(status, error_text))
-        }
+/// `UjumbeSmsClientBuilder` assembles a `UjumbeSmsClient` around a custom `tower` service stack.
+/// Layers are applied like `tower::ServiceBuilder`: the first layer added is the outermost.
+pub struct UjumbeSmsClientBuilder {
+    config: UjumbeSmsConfig,
+    http_client: Option<ReqwestClient>,
+    layers: Vec<LayerFn>,
+    hooks: Vec<Arc<dyn RequestHook>>,
+    sender_registry: Option<SenderRegistry>,
+    #[cfg(feature = "cassette")]
+    cassette: Option<crate::cassette::CassetteMode>,
+}
+
+impl UjumbeSmsClientBuilder {
+    /// Uses a preconfigured reqwest client (proxies, TLS, connection pooling) for the transport
+    pub fn with_http_client(mut self, http_client: ReqwestClient) -> Self {
+        self.http_client = Some(http_client);
+        self
     }
 
-    /// Get messages history: https://ujumbesms.co.ke/api/messages
-    pub async fn get_messages_history(&self) -> Result<MessageHistoryApiResponse, UjumbeSmsError> {
-        let url = format!("{}{}", self.config.base_url, ApiEndpoint::Messages.as_str());
+    /// Wraps the HTTP layer in `layer`, e.g. `tower::timeout::TimeoutLayer` or `tower::retry::RetryLayer`
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<HttpService> + Send + 'static,
+        L::Service: Service<HttpRequest, Response = HttpResponse, Error = BoxError>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        <L::Service as Service<HttpRequest>>::Future: Send + 'static,
+    {
+        self.layers.push(Box::new(move |service: HttpService| {
+            BoxCloneSyncService::new(layer.layer(service))
+        }));
+        self
+    }
 
-        let headers = self.attach_headers()?;
+    /// Registers a hook that sees (and may veto) every request and sees every outcome
+    pub fn with_hook(mut self, hook: impl RequestHook + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
 
-        let response = self.http_client.post(&url).headers(headers).send().await?;
+    /// Checks every outgoing bag against the sender IDs approved for this account
+    pub fn with_sender_registry(mut self, registry: SenderRegistry) -> Self {
+        self.sender_registry = Some(registry);
+        self
+    }
 
-        if response.status().is_success() {
-            let api_response = response.json::<MessageHistoryApiResponse>().await?;
-            Ok(api_response)
-        } else {
-            let status = response.status().to_string();
-            let error_text = response.text().await?;
-            Err(UjumbeSmsError::ApiError(status, error_text))
+    /// Records HTTP exchanges to, or replays them from, a cassette file
+    #[cfg(feature = "cassette")]
+    pub fn with_cassette(mut self, mode: crate::cassette::CassetteMode) -> Self {
+        self.cassette = Some(mode);
+        self
+    }
+
+    pub fn build(self) -> Result<UjumbeSmsClient, UjumbeSmsError> {
+        let http_client = match self.http_client {
+            Some(http_client) => http_client,
+            None => ReqwestClient::builder()
+                .build()
+                .map_err(UjumbeSmsError::from)?,
+        };
+
+        let mut service = BoxCloneSyncService::new(ReqwestService::new(http_client));
+        #[cfg(feature = "cassette")]
+        if let Some(mode) = self.cassette {
+            let cassette = crate::cassette::Cassette::open(mode, &self.config.email)?;
+            service =
+                BoxCloneSyncService::new(crate::cassette::CassetteService::new(cassette, service));
+        }
+        for layer in self.layers.into_iter().rev() {
+            service = layer(service);
         }
+
+        Ok(UjumbeSmsClient {
+            config: self.config,
+            service,
+            hooks: self.hooks.into(),
+            sender_registry: self.sender_registry.map(Arc::new),
+        })
     }
 }
 
@@ -0,0 +1,441 @@
+use crate::client::ApiEndpoint;
+use crate::hooks::{HookDecision, RequestHook};
+use crate::models::{MessageBag, MessageRequest};
+use crate::sending_window::MessageCategory;
+use serde::Serialize;
+
+/// Footer appended to promotional messages by `ComplianceRules::auto_fix`
+pub const DEFAULT_OPT_OUT_FOOTER: &str = "Reply STOP to opt out";
+
+/// `Violation` is a single rule broken by a message bag
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "rule", content = "detail", rename_all = "snake_case")]
+pub enum Violation {
+    /// The message contains a banned word or phrase
+    BannedWord(String),
+    /// A promotional message does not name its sender
+    MissingSenderIdentification,
+    /// A promotional message does not tell recipients how to opt out
+    MissingOptOutFooter,
+    /// The message links to a domain outside the allow-list
+    DisallowedUrl(String),
+}
+
+/// `BagReport` lists the violations found in one message bag
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BagReport {
+    /// Position of the bag in `MessageRequest.data`
+    pub index: usize,
+    /// Violations still present in the bag
+    pub violations: Vec<Violation>,
+    /// Violations corrected by `ComplianceRules::auto_fix`
+    pub fixed: Vec<Violation>,
+}
+
+/// `ComplianceReport` is the outcome of checking a `MessageRequest`, one entry per bag
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ComplianceReport {
+    pub bags: Vec<BagReport>,
+}
+
+impl ComplianceReport {
+    /// Whether every bag is free of (remaining) violations
+    pub fn is_compliant(&self) -> bool {
+        self.bags.iter().all(|bag| bag.violations.is_empty())
+    }
+
+    /// All remaining violations with the index of the bag they were found in
+    pub fn violations(&self) -> impl Iterator<Item = (usize, &Violation)> {
+        self.bags
+            .iter()
+            .flat_map(|bag| bag.violations.iter().map(move |v| (bag.index, v)))
+    }
+}
+
+/// `ComplianceRules` is a configurable rule set checked against outgoing messages.
+/// Banned words and the URL allow-list apply to every message; sender identification
+/// and the opt-out footer only to promotional ones.
+#[derive(Debug, Clone, Default)]
+pub struct ComplianceRules {
+    banned_words: Vec<String>,
+    sender_identification: bool,
+    opt_out_footer: Option<String>,
+    allowed_domains: Option<Vec<String>>,
+}
+
+impl ComplianceRules {
+    /// An empty rule set that accepts everything
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Words or phrases that may not appear in a message, matched case-insensitively on word boundaries
+    pub fn with_banned_words<I, S>(mut self, words: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.banned_words.extend(
+            words
+                .into_iter()
+                .map(Into::into)
+                .filter(|w| !w.trim().is_empty()),
+        );
+        self
+    }
+
+    /// Requires promotional messages to mention their sender ID
+    pub fn with_sender_identification(mut self, required: bool) -> Self {
+        self.sender_identification = required;
+        self
+    }
+
+    /// Requires promotional messages to contain `footer` (ignoring case), appending it when auto-fixing
+    pub fn with_opt_out_footer(mut self, footer: impl Into<String>) -> Self {
+        self.opt_out_footer = Some(footer.into());
+        self
+    }
+
+    /// Only allows links to these domains (and their subdomains)
+    pub fn with_allowed_domains<I, S>(mut self, domains: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_domains = Some(
+            domains
+                .into_iter()
+                .map(|d| d.into().trim().trim_start_matches('.').to_lowercase())
+                .collect(),
+        );
+        self
+    }
+
+    /// Checks a single message bag
+    pub fn check_bag(&self, bag: &MessageBag, category: MessageCategory) -> Vec<Violation> {
+        let mut violations: Vec<Violation> = self
+            .banned_words
+            .iter()
+            .filter(|word| !find_words(&bag.message, word).is_empty())
+            .map(|word| Violation::BannedWord(word.clone()))
+            .collect();
+
+        if category == MessageCategory::Promotional {
+            if self.sender_identification && !mentions_sender(bag) {
+                violations.push(Violation::MissingSenderIdentification);
+            }
+            if let Some(footer) = &self.opt_out_footer {
+                if find_words(&bag.message, footer).is_empty() {
+                    violations.push(Violation::MissingOptOutFooter);
+                }
+            }
+        }
+
+        if let Some(allowed) = &self.allowed_domains {
+            violations.extend(
+                urls(&bag.message)
+                    .filter(|url| !is_allowed(url, allowed))
+                    .map(|url| Violation::DisallowedUrl(url.to_string())),
+            );
+        }
+
+        violations
+    }
+
+    /// Checks every bag of `request` without modifying it
+    pub fn check(&self, request: &MessageRequest, category: MessageCategory) -> ComplianceReport {
+        ComplianceReport {
+            bags: request
+                .data
+                .iter()
+                .enumerate()
+                .map(|(index, c)| BagReport {
+                    index,
+                    violations: self.check_bag(&c.message_bag, category),
+                    fixed: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Corrects what can be corrected: banned words are masked with `*`, the sender ID is
+    /// prefixed and the opt-out footer appended. Disallowed URLs are left in place and reported.
+    pub fn auto_fix(
+        &self,
+        mut request: MessageRequest,
+        category: MessageCategory,
+    ) -> (MessageRequest, ComplianceReport) {
+        let mut report = ComplianceReport::default();
+
+        for (index, container) in request.data.iter_mut().enumerate() {
+            let bag = &mut container.message_bag;
+            let found = self.check_bag(bag, category);
+
+            for violation in &found {
+                match violation {
+                    Violation::BannedWord(word) => {
+                        bag.message = mask_words(&bag.message, word);
+                    }
+                    Violation::MissingSenderIdentification => {
+                        bag.message = format!("{}: {}", bag.sender, bag.message);
+                    }
+                    Violation::MissingOptOutFooter => {
+                        let footer = self
+                            .opt_out_footer
+                            .as_deref()
+                            .unwrap_or(DEFAULT_OPT_OUT_FOOTER);
+                        bag.message = format!("{} {footer}", bag.message.trim_end());
+                    }
+                    Violation::DisallowedUrl(_) => {}
+                }
+            }
+
+            let violations = self.check_bag(bag, category);
+            let fixed = found
+                .into_iter()
+                .filter(|v| !violations.contains(v))
+                .collect();
+            report.bags.push(BagReport {
+                index,
+                violations,
+                fixed,
+            });
+        }
+
+        (request, report)
+    }
+}
+
+/// `ComplianceHook` vetoes messaging requests that break a rule set, or whose body it cannot parse
+pub struct ComplianceHook {
+    rules: ComplianceRules,
+    category: MessageCategory,
+}
+
+impl ComplianceHook {
+    pub fn new(rules: ComplianceRules, category: MessageCategory) -> Self {
+        ComplianceHook { rules, category }
+    }
+}
+
+impl RequestHook for ComplianceHook {
+    fn before_request(&self, endpoint: &ApiEndpoint, request: Option<&str>) -> HookDecision {
+        let (ApiEndpoint::Messaging, Some(body)) = (endpoint, request) else {
+            return HookDecision::Proceed;
+        };
+        // A body the rules cannot read cannot be shown to be compliant
+        let request = match serde_json::from_str::<MessageRequest>(body) {
+            Ok(request) => request,
+            Err(e) => {
+                return HookDecision::Veto(format!(
+                    "compliance check failed (unreadable body: {e})"
+                ))
+            }
+        };
+
+        let report = self.rules.check(&request, self.category);
+        if report.is_compliant() {
+            return HookDecision::Proceed;
+        }
+        let reasons: Vec<String> = report
+            .violations()
+            .map(|(index, violation)| format!("bag {index}: {violation:?}"))
+            .collect();
+        HookDecision::Veto(format!("compliance check failed ({})", reasons.join(", ")))
+    }
+}
+
+/// Whether the message names its sender ID (ignoring case)
+fn mentions_sender(bag: &MessageBag) -> bool {
+    let sender = bag.sender.trim();
+    !sender.is_empty() && !find_words(&bag.message, sender).is_empty()
+}
+
+/// Character ranges of the case-insensitive, whole-word occurrences of `needle` in `haystack`
+fn find_words(haystack: &str, needle: &str) -> Vec<std::ops::Range<usize>> {
+    // One lowercase char per input char keeps the ranges aligned with `haystack.chars()`
+    let fold = |s: &str| -> Vec<char> {
+        s.chars()
+            .map(|c| c.to_lowercase().next().unwrap_or(c))
+            .collect()
+    };
+    let hay = fold(haystack);
+    let needle = fold(needle.trim());
+
+    let mut found = Vec::new();
+    if needle.is_empty() || needle.len() > hay.len() {
+        return found;
+    }
+    let boundary = |c: Option<&char>| c.is_none_or(|c| !c.is_alphanumeric());
+    for start in 0..=hay.len() - needle.len() {
+        let end = start + needle.len();
+        if hay[start..end] == needle[..]
+            && boundary(start.checked_sub(1).and_then(|i| hay.get(i)))
+            && boundary(hay.get(end))
+        {
+            found.push(start..end);
+        }
+    }
+    found
+}
+
+/// Replaces each whole-word occurrence of `word` with asterisks
+fn mask_words(message: &str, word: &str) -> String {
+    let ranges = find_words(message, word);
+    message
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if ranges.iter().any(|r| r.contains(&i)) {
+                '*'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Links in a message: tokens starting with `http://`, `https://` or `www.`
+fn urls(message: &str) -> impl Iterator<Item = &str> {
+    message
+        .split_whitespace()
+        .map(|token| token.trim_end_matches(['.', ',', '!', '?', ')', ';', ':']))
+        .filter(|token| {
+            let lower = token.to_lowercase();
+            lower.starts_with("http://")
+                || lower.starts_with("https://")
+                || lower.starts_with("www.")
+        })
+}
+
+fn is_allowed(url: &str, allowed: &[String]) -> bool {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = without_scheme
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = host.split(':').next().unwrap_or_default().to_lowercase();
+
+    allowed
+        .iter()
+        .any(|domain| host == *domain || host.ends_with(&format!(".{domain}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> ComplianceRules {
+        ComplianceRules::new()
+            .with_banned_words(["casino", "free money"])
+            .with_sender_identification(true)
+            .with_opt_out_footer(DEFAULT_OPT_OUT_FOOTER)
+            .with_allowed_domains(["example.co.ke"])
+    }
+
+    fn request(messages: &[&str]) -> MessageRequest {
+        let mut request = MessageRequest::new();
+        for message in messages {
+            request.add_message_bag(
+                "254712345678".to_string(),
+                message.to_string(),
+                "ACME".to_string(),
+            );
+        }
+        request
+    }
+
+    #[test]
+    fn test_check_reports_violations_per_bag() {
+        let messages = request(&[
+            "ACME: 20% off this weekend at https://shop.example.co.ke/deals. Reply STOP to opt out",
+            "Win FREE MONEY at our Casino! http://bit.ly/xyz",
+            "Casinos are not casino-free",
+            "ACME: Don't stop now! reply stop to opt out",
+            "ACME: Don't stop now!",
+        ]);
+
+        let report = rules().check(&messages, MessageCategory::Promotional);
+        assert!(!report.is_compliant());
+        assert!(report.bags[0].violations.is_empty());
+        assert_eq!(
+            report.bags[1].violations,
+            vec![
+                Violation::BannedWord("casino".to_string()),
+                Violation::BannedWord("free money".to_string()),
+                Violation::MissingSenderIdentification,
+                Violation::MissingOptOutFooter,
+                Violation::DisallowedUrl("http://bit.ly/xyz".to_string()),
+            ]
+        );
+        // "Casinos" is a different word; "casino-free" contains the whole word
+        assert_eq!(
+            report.bags[2].violations[0],
+            Violation::BannedWord("casino".to_string())
+        );
+        // The footer itself is required, not just the word STOP
+        assert!(report.bags[3].violations.is_empty());
+        assert_eq!(
+            report.bags[4].violations,
+            vec![Violation::MissingOptOutFooter]
+        );
+
+        // Transactional messages only face the banned word and URL rules
+        let report = rules().check(
+            &request(&["Your OTP is 1234"]),
+            MessageCategory::Transactional,
+        );
+        assert!(report.is_compliant());
+    }
+
+    #[test]
+    fn test_auto_fix() {
+        let (fixed, report) = rules().auto_fix(
+            request(&["Visit our casino at www.bit.ly/win."]),
+            MessageCategory::Promotional,
+        );
+
+        assert_eq!(
+            fixed.data[0].message_bag.message,
+            "ACME: Visit our ****** at www.bit.ly/win. Reply STOP to opt out"
+        );
+        assert_eq!(
+            report.bags[0].fixed,
+            vec![
+                Violation::BannedWord("casino".to_string()),
+                Violation::MissingSenderIdentification,
+                Violation::MissingOptOutFooter,
+            ]
+        );
+        assert_eq!(
+            report.bags[0].violations,
+            vec![Violation::DisallowedUrl("www.bit.ly/win".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_hook_vetoes_non_compliant_messages() {
+        let hook = ComplianceHook::new(rules(), MessageCategory::Transactional);
+        let body = |message: &str| serde_json::to_string(&request(&[message])).unwrap();
+
+        assert_eq!(
+            hook.before_request(&ApiEndpoint::Messaging, Some(&body("Your OTP is 1234"))),
+            HookDecision::Proceed
+        );
+        assert!(matches!(
+            hook.before_request(&ApiEndpoint::Messaging, Some(&body("casino night"))),
+            HookDecision::Veto(reason) if reason.contains("BannedWord")
+        ));
+        assert!(matches!(
+            hook.before_request(&ApiEndpoint::Messaging, Some("not json")),
+            HookDecision::Veto(reason) if reason.contains("unreadable body")
+        ));
+        assert_eq!(
+            hook.before_request(&ApiEndpoint::Balances, None),
+            HookDecision::Proceed
+        );
+    }
+}
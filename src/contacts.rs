@@ -0,0 +1,574 @@
+use crate::errors::UjumbeSmsError;
+use crate::phone::{is_plausible_number, normalize_number};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+#[cfg(feature = "csv")]
+use std::io::Read;
+#[cfg(feature = "sqlite")]
+use std::path::Path;
+
+/// `Contact` is a recipient in a contact book, keyed by its normalised number
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    pub number: String,
+    pub name: Option<String>,
+    pub tags: BTreeSet<String>,
+    /// Custom fields, e.g. `city` or `account_id`
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Contact {
+    pub fn new(number: &str) -> Self {
+        Contact {
+            number: normalize_number(number),
+            ..Contact::default()
+        }
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.insert(tag.into());
+        self
+    }
+
+    pub fn with_field(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.fields.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+}
+
+/// `Audience` selects recipients from a `ContactStore`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Audience {
+    /// Members of a group
+    Group(String),
+    /// Contacts with this tag
+    Tag(String),
+    /// Contacts with every one of these tags
+    AllTags(Vec<String>),
+    /// Contacts with at least one of these tags
+    AnyTag(Vec<String>),
+}
+
+impl Audience {
+    fn matches(&self, contact: &Contact) -> bool {
+        match self {
+            Audience::Group(_) => false,
+            Audience::Tag(tag) => contact.has_tag(tag),
+            Audience::AllTags(tags) => tags.iter().all(|tag| contact.has_tag(tag)),
+            Audience::AnyTag(tags) => tags.iter().any(|tag| contact.has_tag(tag)),
+        }
+    }
+}
+
+/// `ImportSummary` describes the result of `ContactStore::import`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub imported: usize,
+    /// Position of each rejected record with the reason, e.g. an invalid number
+    pub skipped: Vec<(usize, String)>,
+}
+
+/// `ContactStore` holds contacts and the groups they belong to.
+/// Numbers are normalised by every method, so any format may be passed in.
+pub trait ContactStore: Send + Sync {
+    fn get(&self, number: &str) -> Result<Option<Contact>, UjumbeSmsError>;
+
+    /// Inserts `contact`, replacing any contact with the same number
+    fn upsert(&self, contact: Contact) -> Result<(), UjumbeSmsError>;
+
+    /// Removes the contact and its group memberships, returning `true` if it existed
+    fn remove(&self, number: &str) -> Result<bool, UjumbeSmsError>;
+
+    /// Every contact, ordered by number
+    fn contacts(&self) -> Result<Vec<Contact>, UjumbeSmsError>;
+
+    /// Adds `number` to `group`, returning `true` if it was not a member already.
+    /// The number does not need to be a contact.
+    fn add_to_group(&self, group: &str, number: &str) -> Result<bool, UjumbeSmsError>;
+
+    fn remove_from_group(&self, group: &str, number: &str) -> Result<bool, UjumbeSmsError>;
+
+    /// Numbers in `group`, ordered
+    fn group_members(&self, group: &str) -> Result<Vec<String>, UjumbeSmsError>;
+
+    fn groups(&self) -> Result<Vec<String>, UjumbeSmsError>;
+
+    /// Numbers selected by `audience`, ordered and without duplicates
+    fn resolve(&self, audience: &Audience) -> Result<Vec<String>, UjumbeSmsError> {
+        match audience {
+            Audience::Group(group) => self.group_members(group),
+            _ => Ok(self
+                .contacts()?
+                .into_iter()
+                .filter(|contact| audience.matches(contact))
+                .map(|contact| contact.number)
+                .collect()),
+        }
+    }
+
+    /// Upserts every contact with a plausible number, e.g. the output of `parse_csv` or `parse_vcard`
+    fn import(&self, contacts: Vec<Contact>) -> Result<ImportSummary, UjumbeSmsError> {
+        let mut summary = ImportSummary::default();
+        for (index, contact) in contacts.into_iter().enumerate() {
+            if is_plausible_number(&contact.number) {
+                self.upsert(contact)?;
+                summary.imported += 1;
+            } else {
+                summary
+                    .skipped
+                    .push((index, format!("invalid number \"{}\"", contact.number)));
+            }
+        }
+        Ok(summary)
+    }
+}
+
+/// Reads contacts from CSV with a header row. The `number` (or `phone`) column is required;
+/// `name` and `tags` (separated by `;`) are optional, and any other column becomes a custom field.
+#[cfg(feature = "csv")]
+pub fn parse_csv<R: Read>(reader: R) -> Result<Vec<Contact>, UjumbeSmsError> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(csv_error)?
+        .iter()
+        .map(|header| header.trim().to_lowercase())
+        .collect();
+    if !headers.iter().any(|h| h == "number" || h == "phone") {
+        return Err(UjumbeSmsError::StorageError(
+            "CSV contacts need a \"number\" or \"phone\" column".to_string(),
+        ));
+    }
+
+    let mut contacts = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(csv_error)?;
+        let mut contact = Contact::default();
+        for (header, value) in headers.iter().zip(record.iter()) {
+            let value = value.trim();
+            match header.as_str() {
+                "number" | "phone" => contact.number = normalize_number(value),
+                "name" if !value.is_empty() => contact.name = Some(value.to_string()),
+                "tags" => contact.tags.extend(
+                    value
+                        .split(';')
+                        .map(str::trim)
+                        .filter(|tag| !tag.is_empty())
+                        .map(str::to_string),
+                ),
+                "name" => {}
+                _ if !value.is_empty() => {
+                    contact.fields.insert(header.clone(), value.to_string());
+                }
+                _ => {}
+            }
+        }
+        contacts.push(contact);
+    }
+    Ok(contacts)
+}
+
+/// Reads contacts from vCard text. `FN` becomes the name, the first `TEL` (preferring
+/// `TYPE=CELL`) the number and `CATEGORIES` the tags. Cards without a `TEL` are left out.
+pub fn parse_vcard(vcard: &str) -> Vec<Contact> {
+    // Folded lines continue with a leading space or tab
+    let mut lines: Vec<String> = Vec::new();
+    for line in vcard.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.trim_end().to_string()),
+        }
+    }
+
+    let mut contacts = Vec::new();
+    let mut card: Option<(Contact, Option<String>, Option<String>)> = None;
+    for line in &lines {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut params = key.split(';');
+        let name = params.next().unwrap_or_default().to_uppercase();
+        let value = value.trim();
+
+        match (name.as_str(), card.as_mut()) {
+            ("BEGIN", _) if value.eq_ignore_ascii_case("VCARD") => {
+                card = Some((Contact::default(), None, None));
+            }
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VCARD") => {
+                let (mut contact, cell, other) = card.take().unwrap_or_default();
+                if let Some(number) = cell.or(other) {
+                    contact.number = normalize_number(&number);
+                    contacts.push(contact);
+                }
+            }
+            ("FN", Some((contact, _, _))) if !value.is_empty() => {
+                contact.name = Some(value.to_string());
+            }
+            ("TEL", Some((_, cell, other))) => {
+                let number = value.trim_start_matches("tel:").to_string();
+                let is_cell = params.any(|param| param.to_uppercase().contains("CELL"));
+                if is_cell && cell.is_none() {
+                    *cell = Some(number);
+                } else if other.is_none() {
+                    *other = Some(number);
+                }
+            }
+            ("CATEGORIES", Some((contact, _, _))) => contact.tags.extend(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string),
+            ),
+            _ => {}
+        }
+    }
+    contacts
+}
+
+#[cfg(feature = "csv")]
+fn csv_error(error: csv::Error) -> UjumbeSmsError {
+    UjumbeSmsError::StorageError(error.to_string())
+}
+
+#[derive(Debug, Default)]
+struct ContactBook {
+    contacts: BTreeMap<String, Contact>,
+    groups: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// `InMemoryContactStore` keeps contacts and groups in process memory
+#[derive(Debug, Default)]
+pub struct InMemoryContactStore {
+    book: Mutex<ContactBook>,
+}
+
+impl InMemoryContactStore {
+    pub fn new() -> Self {
+        InMemoryContactStore::default()
+    }
+}
+
+impl ContactStore for InMemoryContactStore {
+    fn get(&self, number: &str) -> Result<Option<Contact>, UjumbeSmsError> {
+        let book = self.book.lock().expect("contact store lock poisoned");
+        Ok(book.contacts.get(&normalize_number(number)).cloned())
+    }
+
+    fn upsert(&self, mut contact: Contact) -> Result<(), UjumbeSmsError> {
+        let mut book = self.book.lock().expect("contact store lock poisoned");
+        contact.number = normalize_number(&contact.number);
+        book.contacts.insert(contact.number.clone(), contact);
+        Ok(())
+    }
+
+    fn remove(&self, number: &str) -> Result<bool, UjumbeSmsError> {
+        let mut book = self.book.lock().expect("contact store lock poisoned");
+        let number = normalize_number(number);
+        for members in book.groups.values_mut() {
+            members.remove(&number);
+        }
+        Ok(book.contacts.remove(&number).is_some())
+    }
+
+    fn contacts(&self) -> Result<Vec<Contact>, UjumbeSmsError> {
+        let book = self.book.lock().expect("contact store lock poisoned");
+        Ok(book.contacts.values().cloned().collect())
+    }
+
+    fn add_to_group(&self, group: &str, number: &str) -> Result<bool, UjumbeSmsError> {
+        let mut book = self.book.lock().expect("contact store lock poisoned");
+        Ok(book
+            .groups
+            .entry(group.to_string())
+            .or_default()
+            .insert(normalize_number(number)))
+    }
+
+    fn remove_from_group(&self, group: &str, number: &str) -> Result<bool, UjumbeSmsError> {
+        let mut book = self.book.lock().expect("contact store lock poisoned");
+        Ok(book
+            .groups
+            .get_mut(group)
+            .is_some_and(|members| members.remove(&normalize_number(number))))
+    }
+
+    fn group_members(&self, group: &str) -> Result<Vec<String>, UjumbeSmsError> {
+        let book = self.book.lock().expect("contact store lock poisoned");
+        Ok(book
+            .groups
+            .get(group)
+            .map(|members| members.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn groups(&self) -> Result<Vec<String>, UjumbeSmsError> {
+        let book = self.book.lock().expect("contact store lock poisoned");
+        Ok(book
+            .groups
+            .iter()
+            .filter(|(_, members)| !members.is_empty())
+            .map(|(group, _)| group.clone())
+            .collect())
+    }
+}
+
+/// `SqliteContactStore` stores contacts and group memberships in SQLite tables.
+/// Tags and custom fields are kept as JSON.
+#[cfg(feature = "sqlite")]
+pub struct SqliteContactStore {
+    conn: Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteContactStore {
+    /// Opens (or creates) the database at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, UjumbeSmsError> {
+        Self::from_connection(rusqlite::Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, UjumbeSmsError> {
+        Self::from_connection(rusqlite::Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: rusqlite::Connection) -> Result<Self, UjumbeSmsError> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS contacts (
+                number TEXT PRIMARY KEY,
+                name TEXT,
+                tags TEXT NOT NULL,
+                fields TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS contact_groups (
+                group_name TEXT NOT NULL,
+                number TEXT NOT NULL,
+                PRIMARY KEY (group_name, number)
+            );",
+        )?;
+        Ok(SqliteContactStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn contact_from_row(row: &rusqlite::Row) -> rusqlite::Result<(Contact, String, String)> {
+        let contact = Contact {
+            number: row.get(0)?,
+            name: row.get(1)?,
+            ..Contact::default()
+        };
+        Ok((contact, row.get(2)?, row.get(3)?))
+    }
+
+    fn decode(
+        (mut contact, tags, fields): (Contact, String, String),
+    ) -> Result<Contact, UjumbeSmsError> {
+        contact.tags = serde_json::from_str(&tags)?;
+        contact.fields = serde_json::from_str(&fields)?;
+        Ok(contact)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl ContactStore for SqliteContactStore {
+    fn get(&self, number: &str) -> Result<Option<Contact>, UjumbeSmsError> {
+        use rusqlite::OptionalExtension;
+        let conn = self.conn.lock().expect("contact store lock poisoned");
+        let row = conn
+            .query_row(
+                "SELECT number, name, tags, fields FROM contacts WHERE number = ?1",
+                [normalize_number(number)],
+                Self::contact_from_row,
+            )
+            .optional()?;
+        row.map(Self::decode).transpose()
+    }
+
+    fn upsert(&self, contact: Contact) -> Result<(), UjumbeSmsError> {
+        let conn = self.conn.lock().expect("contact store lock poisoned");
+        conn.execute(
+            "INSERT OR REPLACE INTO contacts (number, name, tags, fields) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![
+                normalize_number(&contact.number),
+                contact.name,
+                serde_json::to_string(&contact.tags)?,
+                serde_json::to_string(&contact.fields)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn remove(&self, number: &str) -> Result<bool, UjumbeSmsError> {
+        let conn = self.conn.lock().expect("contact store lock poisoned");
+        let number = normalize_number(number);
+        conn.execute("DELETE FROM contact_groups WHERE number = ?1", [&number])?;
+        let removed = conn.execute("DELETE FROM contacts WHERE number = ?1", [&number])?;
+        Ok(removed > 0)
+    }
+
+    fn contacts(&self) -> Result<Vec<Contact>, UjumbeSmsError> {
+        let conn = self.conn.lock().expect("contact store lock poisoned");
+        let mut stmt =
+            conn.prepare("SELECT number, name, tags, fields FROM contacts ORDER BY number")?;
+        let rows = stmt.query_map([], Self::contact_from_row)?;
+        rows.map(|row| Self::decode(row?)).collect()
+    }
+
+    fn add_to_group(&self, group: &str, number: &str) -> Result<bool, UjumbeSmsError> {
+        let conn = self.conn.lock().expect("contact store lock poisoned");
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO contact_groups (group_name, number) VALUES (?1, ?2)",
+            [group, &normalize_number(number)],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    fn remove_from_group(&self, group: &str, number: &str) -> Result<bool, UjumbeSmsError> {
+        let conn = self.conn.lock().expect("contact store lock poisoned");
+        let removed = conn.execute(
+            "DELETE FROM contact_groups WHERE group_name = ?1 AND number = ?2",
+            [group, &normalize_number(number)],
+        )?;
+        Ok(removed > 0)
+    }
+
+    fn group_members(&self, group: &str) -> Result<Vec<String>, UjumbeSmsError> {
+        let conn = self.conn.lock().expect("contact store lock poisoned");
+        let mut stmt = conn
+            .prepare("SELECT number FROM contact_groups WHERE group_name = ?1 ORDER BY number")?;
+        let rows = stmt.query_map([group], |row| row.get(0))?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    fn groups(&self) -> Result<Vec<String>, UjumbeSmsError> {
+        let conn = self.conn.lock().expect("contact store lock poisoned");
+        let mut stmt =
+            conn.prepare("SELECT DISTINCT group_name FROM contact_groups ORDER BY group_name")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "csv")]
+    const CSV: &str = "Number,Name,Tags,City\n\
+                       0711111111,Amina,vip; nairobi,Nairobi\n\
+                       +254722222222,Brian,nairobi,\n\
+                       12345,Broken,,\n";
+
+    const VCARD: &str = "BEGIN:VCARD\r\n\
+                         VERSION:3.0\r\n\
+                         FN:Chebet\r\n\
+                         TEL;TYPE=HOME:+254 20 1234567\r\n\
+                         TEL;TYPE=CELL:0733 333 333\r\n\
+                         CATEGORIES:vip,mombasa\r\n\
+                         END:VCARD\r\n\
+                         BEGIN:VCARD\r\n\
+                         FN:No Phone\r\n\
+                         END:VCARD\r\n";
+
+    /// The contacts in `CSV`
+    fn imported() -> Vec<Contact> {
+        let contact = |number: &str, name: &str, tags: &[&str], city: Option<&str>| Contact {
+            number: normalize_number(number),
+            name: Some(name.to_string()),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            fields: city
+                .map(|city| ("city".to_string(), city.to_string()))
+                .into_iter()
+                .collect(),
+        };
+        vec![
+            contact("0711111111", "Amina", &["vip", "nairobi"], Some("Nairobi")),
+            contact("+254722222222", "Brian", &["nairobi"], None),
+            contact("12345", "Broken", &[], None),
+        ]
+    }
+
+    fn check_store(store: &dyn ContactStore) {
+        let summary = store.import(imported()).unwrap();
+        assert_eq!(summary.imported, 2);
+        assert_eq!(summary.skipped.len(), 1);
+        assert_eq!(store.import(parse_vcard(VCARD)).unwrap().imported, 1);
+
+        let amina = store.get("+254711111111").unwrap().unwrap();
+        assert_eq!(amina.name.as_deref(), Some("Amina"));
+        assert!(amina.has_tag("vip") && amina.has_tag("nairobi"));
+        assert_eq!(amina.fields["city"], "Nairobi");
+        let chebet = store.get("254733333333").unwrap().unwrap();
+        assert_eq!(chebet.name.as_deref(), Some("Chebet"));
+
+        assert!(store.add_to_group("staff", "0722222222").unwrap());
+        assert!(!store.add_to_group("staff", "254722222222").unwrap());
+        assert!(store.add_to_group("staff", "254744444444").unwrap());
+        assert_eq!(store.groups().unwrap(), vec!["staff"]);
+
+        assert_eq!(
+            store.resolve(&Audience::Tag("vip".into())).unwrap(),
+            vec!["254711111111", "254733333333"]
+        );
+        assert_eq!(
+            store
+                .resolve(&Audience::AllTags(vec!["vip".into(), "nairobi".into()]))
+                .unwrap(),
+            vec!["254711111111"]
+        );
+        assert_eq!(
+            store
+                .resolve(&Audience::AnyTag(vec!["mombasa".into(), "nairobi".into()]))
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(
+            store.resolve(&Audience::Group("staff".into())).unwrap(),
+            vec!["254722222222", "254744444444"]
+        );
+
+        assert!(store.remove("254722222222").unwrap());
+        assert_eq!(store.group_members("staff").unwrap(), vec!["254744444444"]);
+        assert!(store.remove_from_group("staff", "254744444444").unwrap());
+        assert!(store.groups().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_in_memory_contact_store() {
+        check_store(&InMemoryContactStore::new());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_contact_store() {
+        check_store(&SqliteContactStore::open_in_memory().unwrap());
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_parse_csv() {
+        assert_eq!(parse_csv(CSV.as_bytes()).unwrap(), imported());
+        assert!(matches!(
+            parse_csv("name\nAmina\n".as_bytes()),
+            Err(UjumbeSmsError::StorageError(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_vcard() {
+        // Folded lines are joined
+        let contacts = parse_vcard("BEGIN:VCARD\nFN:Long\n  Name\nTEL:0711111111\nEND:VCARD\n");
+        assert_eq!(contacts[0].name.as_deref(), Some("Long Name"));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::client::UjumbeSmsClient;
+use crate::errors::UjumbeSmsError;
+use crate::models::{MessageHistoryApiResponse, MessageSent};
+use crate::phone::normalize_number;
+use std::collections::BTreeMap;
+
+/// `ConversationIndex` groups history rows by recipient, so everything sent to one number
+/// can be read in order. Numbers are normalised, so `+254711111111`, `0711111111` and
+/// `254711111111` share a conversation.
+#[derive(Debug, Clone, Default)]
+pub struct ConversationIndex {
+    conversations: BTreeMap<String, Vec<MessageSent>>,
+}
+
+impl ConversationIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indexes every row of a history page
+    pub fn from_history(history: &MessageHistoryApiResponse) -> Self {
+        let mut index = ConversationIndex::new();
+        index.add_page(history);
+        index
+    }
+
+    /// Fetches up to `max_pages` history pages and indexes them
+    pub async fn load(client: &UjumbeSmsClient, max_pages: u32) -> Result<Self, UjumbeSmsError> {
+        let mut index = ConversationIndex::new();
+        for page in 1..=max_pages {
+            let history = client.get_messages_history_page(page).await?;
+            index.add_page(&history);
+            if history.items.data.is_empty()
+                || history.items.current_page >= history.items.last_page
+            {
+                break;
+            }
+        }
+        Ok(index)
+    }
+
+    pub fn add_page(&mut self, history: &MessageHistoryApiResponse) {
+        for message in &history.items.data {
+            self.insert(message.clone());
+        }
+    }
+
+    /// Adds `message` in `created_at` order. A row with the same `id` is replaced, so re-adding
+    /// a page picks up status changes.
+    pub fn insert(&mut self, message: MessageSent) {
+        let conversation = self
+            .conversations
+            .entry(normalize_number(&message.number))
+            .or_default();
+        conversation.retain(|m| m.id != message.id);
+        // History timestamps are `YYYY-MM-DD HH:MM:SS`, so they sort as strings
+        let position = conversation.partition_point(|m| {
+            (m.created_at.as_str(), m.id) <= (message.created_at.as_str(), message.id)
+        });
+        conversation.insert(position, message);
+    }
+
+    /// Messages sent to `number` in any format, oldest first
+    pub fn conversation(&self, number: &str) -> &[MessageSent] {
+        self.conversations
+            .get(&normalize_number(number))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The most recent message sent to `number`
+    pub fn latest(&self, number: &str) -> Option<&MessageSent> {
+        self.conversation(number).last()
+    }
+
+    /// Every normalised number with at least one message, in ascending order
+    pub fn numbers(&self) -> impl Iterator<Item = &str> {
+        self.conversations.keys().map(String::as_str)
+    }
+
+    /// Number of distinct recipients
+    pub fn len(&self) -> usize {
+        self.conversations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conversations.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{history, sent};
+
+    #[test]
+    fn test_groups_by_normalised_number_in_order() {
+        let page = history([
+            sent(4)
+                .number("+254711111111")
+                .status("Pending")
+                .created_at("2025-07-21 09:00:00"),
+            sent(3)
+                .number("254722222222")
+                .created_at("2025-07-20 12:00:00"),
+            sent(2)
+                .number("0711111111")
+                .created_at("2025-07-20 10:00:00"),
+            sent(1)
+                .number("254711111111")
+                .created_at("2025-07-19 08:00:00"),
+        ]);
+        let mut index = ConversationIndex::from_history(&page);
+
+        assert_eq!(index.len(), 2);
+        assert_eq!(
+            index.numbers().collect::<Vec<_>>(),
+            vec!["254711111111", "254722222222"]
+        );
+        let ids: Vec<i64> = index
+            .conversation("0711 111 111")
+            .iter()
+            .map(|m| m.id)
+            .collect();
+        assert_eq!(ids, vec![1, 2, 4]);
+        assert!(index.conversation("254733333333").is_empty());
+
+        // Re-indexing a row replaces it instead of duplicating it
+        index.add_page(&history([sent(4)
+            .number("254711111111")
+            .created_at("2025-07-21 09:00:00")]));
+        assert_eq!(index.conversation("254711111111").len(), 3);
+        assert_eq!(
+            index.latest("+254711111111").unwrap().status,
+            "DeliveredToTerminal"
+        );
+
+        // `get_messages_by_number` uses the same normalisation, and no longer matches substrings
+        assert_eq!(page.get_messages_by_number("+254711111111").len(), 3);
+        assert!(page.get_messages_by_number("2547").is_empty());
+    }
+}
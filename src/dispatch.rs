@@ -0,0 +1,397 @@
+use crate::client::UjumbeSmsClient;
+use crate::errors::UjumbeSmsError;
+use crate::models::{MessageRequest, MessagingApiResponse};
+use crate::rate_limit::RateLimiter;
+use crate::sending_window::MessageCategory;
+use futures_util::stream::FuturesUnordered;
+use futures_util::StreamExt;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::{sleep_until, Instant};
+
+/// `Priority` selects the lane a request is queued in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Priority {
+    /// OTPs, alerts and other transactional traffic
+    High,
+    Normal,
+    /// Promotional batches
+    Low,
+}
+
+impl Priority {
+    /// Every priority, highest first
+    pub const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl From<MessageCategory> for Priority {
+    fn from(category: MessageCategory) -> Self {
+        match category {
+            MessageCategory::Transactional => Priority::High,
+            MessageCategory::Promotional => Priority::Low,
+        }
+    }
+}
+
+/// `LaneConfig` is the concurrency and rate budget of one priority lane
+#[derive(Debug, Clone)]
+pub struct LaneConfig {
+    concurrency: usize,
+    /// Minimum time between two requests of the lane starting
+    spacing: Option<Duration>,
+}
+
+impl LaneConfig {
+    /// A lane with at most `concurrency` requests in flight and no rate limit
+    pub fn new(concurrency: usize) -> Self {
+        LaneConfig {
+            concurrency: concurrency.max(1),
+            spacing: None,
+        }
+    }
+
+    /// Starts at most `requests` requests of this lane in any `per` period, spaced evenly.
+    /// A lane waiting for its next slot does not hold any in-flight capacity.
+    pub fn with_rate_limit(mut self, requests: u32, per: Duration) -> Self {
+        self.spacing = Some(RateLimiter::new(requests, per).spacing());
+        self
+    }
+}
+
+struct Job {
+    priority: Priority,
+    request: MessageRequest,
+    reply: oneshot::Sender<Result<MessagingApiResponse, UjumbeSmsError>>,
+}
+
+impl std::fmt::Debug for Job {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Job")
+            .field("priority", &self.priority)
+            .finish_non_exhaustive()
+    }
+}
+
+/// `PriorityDispatcher` queues requests in priority lanes that share one `UjumbeSmsClient`.
+/// Free capacity goes to the highest lane with a backlog, so transactional messages overtake
+/// queued promotional batches; a lower lane only starts requests while the lanes above it are
+/// empty, at their concurrency limit or waiting for a rate slot. Requests already in flight are
+/// never interrupted.
+pub struct PriorityDispatcher {
+    client: UjumbeSmsClient,
+    lanes: [LaneConfig; 3],
+    max_in_flight: usize,
+}
+
+impl PriorityDispatcher {
+    /// High, normal and low lanes with 4, 2 and 1 requests in flight
+    pub fn new(client: UjumbeSmsClient) -> Self {
+        PriorityDispatcher {
+            client,
+            lanes: [LaneConfig::new(4), LaneConfig::new(2), LaneConfig::new(1)],
+            max_in_flight: usize::MAX,
+        }
+    }
+
+    pub fn with_lane(mut self, priority: Priority, lane: LaneConfig) -> Self {
+        self.lanes[priority.index()] = lane;
+        self
+    }
+
+    /// Limit on requests in flight across all lanes, e.g. an account-wide concurrency limit
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    /// Starts the dispatcher on the current Tokio runtime. It stops once every handle is
+    /// dropped and the queued requests have been sent.
+    pub fn spawn(self) -> DispatchHandle {
+        let (jobs_tx, jobs_rx) = mpsc::unbounded_channel();
+        tokio::spawn(schedule(self, jobs_rx));
+        DispatchHandle { jobs: jobs_tx }
+    }
+}
+
+async fn schedule(dispatcher: PriorityDispatcher, mut jobs: mpsc::UnboundedReceiver<Job>) {
+    let mut queues: [VecDeque<Job>; 3] = Default::default();
+    let mut lane_in_flight = [0usize; 3];
+    // When each rate-limited lane may start its next request
+    let mut lane_ready: [Option<Instant>; 3] = [None; 3];
+    let mut wake_at: Option<Instant> = None;
+    let mut in_flight = FuturesUnordered::new();
+    let mut open = true;
+
+    loop {
+        tokio::select! {
+            job = jobs.recv(), if open => match job {
+                Some(job) => {
+                    queues[job.priority.index()].push_back(job);
+                    // Queue everything already submitted so priorities are compared together
+                    while let Ok(job) = jobs.try_recv() {
+                        queues[job.priority.index()].push_back(job);
+                    }
+                }
+                None => open = false,
+            },
+            Some(priority) = in_flight.next(), if !in_flight.is_empty() => {
+                lane_in_flight[Priority::index(priority)] -= 1;
+            }
+            _ = sleep_until(wake_at.unwrap_or_else(Instant::now)), if wake_at.is_some() => {}
+            else => break,
+        }
+        wake_at = None;
+
+        // Start requests highest lane first. A lane at its concurrency limit or waiting for its
+        // rate slot leaves the free capacity to the lanes below it.
+        let now = Instant::now();
+        for priority in Priority::ALL {
+            let lane = &dispatcher.lanes[priority.index()];
+            while !queues[priority.index()].is_empty()
+                && lane_in_flight[priority.index()] < lane.concurrency
+                && in_flight.len() < dispatcher.max_in_flight
+            {
+                // Wait for the lane's rate slot here, before the request takes any capacity
+                if let Some(ready) = lane_ready[priority.index()].filter(|ready| *ready > now) {
+                    wake_at = Some(wake_at.map_or(ready, |wake_at| wake_at.min(ready)));
+                    break;
+                }
+                let Some(job) = queues[priority.index()].pop_front() else {
+                    break;
+                };
+
+                lane_in_flight[priority.index()] += 1;
+                lane_ready[priority.index()] = lane.spacing.map(|spacing| now + spacing);
+                let client = dispatcher.client.clone();
+                in_flight.push(async move {
+                    let result = client.send_messages(job.request).await;
+                    // The caller may have stopped waiting
+                    let _ = job.reply.send(result);
+                    job.priority
+                });
+            }
+        }
+
+        if !open && in_flight.is_empty() && queues.iter().all(VecDeque::is_empty) {
+            break;
+        }
+    }
+}
+
+/// `DispatchHandle` submits requests to a running `PriorityDispatcher`; clone it freely
+#[derive(Debug, Clone)]
+pub struct DispatchHandle {
+    jobs: mpsc::UnboundedSender<Job>,
+}
+
+impl DispatchHandle {
+    /// Queues `request` in the lane for `priority` and waits for the response.
+    /// A `MessageCategory` may be passed as the priority. Fails with
+    /// `UjumbeSmsError::TaskStopped` if the dispatcher is no longer running, e.g. after its
+    /// runtime shut down.
+    pub async fn send(
+        &self,
+        priority: impl Into<Priority>,
+        request: MessageRequest,
+    ) -> Result<MessagingApiResponse, UjumbeSmsError> {
+        let stopped = || UjumbeSmsError::TaskStopped("priority dispatcher stopped".to_string());
+        let (reply, response) = oneshot::channel();
+        self.jobs
+            .send(Job {
+                priority: priority.into(),
+                request,
+                reply,
+            })
+            .map_err(|_| stopped())?;
+        response.await.map_err(|_| stopped())?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{messaging_body, recording_client};
+    use crate::UjumbeSmsConfig;
+    use mockito::Server;
+
+    fn request(message: &str) -> MessageRequest {
+        let mut request = MessageRequest::new();
+        request.add_message_bag(
+            "254712345678".to_string(),
+            message.to_string(),
+            "UjumbeSMS".to_string(),
+        );
+        request
+    }
+
+    #[test]
+    fn test_high_priority_overtakes_queued_batches() {
+        let mut server = Server::new();
+        let url = server.url();
+        // Submissions all queue before the dispatcher task runs on a single-threaded runtime
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let _mock = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_body(messaging_body(1))
+                .expect(5)
+                .create();
+
+            let (client, order) = recording_client(url);
+
+            let dispatcher = PriorityDispatcher::new(client)
+                .with_lane(
+                    Priority::Low,
+                    LaneConfig::new(1).with_rate_limit(100, Duration::from_secs(1)),
+                )
+                .with_max_in_flight(1)
+                .spawn();
+
+            let submissions = [
+                (Priority::Low, "promo 1"),
+                (Priority::Low, "promo 2"),
+                (Priority::Normal, "reminder"),
+                (Priority::Low, "promo 3"),
+                (Priority::High, "otp"),
+            ];
+            let tasks: Vec<_> = submissions
+                .into_iter()
+                .map(|(priority, message)| {
+                    let dispatcher = dispatcher.clone();
+                    let request = request(message);
+                    tokio::spawn(async move { dispatcher.send(priority, request).await })
+                })
+                .collect();
+            for task in tasks {
+                assert!(task.await.unwrap().is_ok());
+            }
+
+            assert_eq!(
+                *order.lock().unwrap(),
+                vec!["otp", "reminder", "promo 1", "promo 2", "promo 3"]
+            );
+            assert_eq!(
+                Priority::from(MessageCategory::Transactional),
+                Priority::High
+            );
+        });
+    }
+
+    #[test]
+    fn test_rate_limited_lane_does_not_hold_capacity() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let _mock = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_body(messaging_body(1))
+                .create();
+
+            let (client, sent) = recording_client(url);
+            let dispatcher = PriorityDispatcher::new(client)
+                .with_lane(
+                    Priority::Low,
+                    LaneConfig::new(1).with_rate_limit(1, Duration::from_secs(60)),
+                )
+                .with_max_in_flight(1)
+                .spawn();
+
+            dispatcher
+                .send(Priority::Low, request("promo 1"))
+                .await
+                .unwrap();
+            // The second promo waits a minute for its slot without taking the only in-flight slot
+            let low = dispatcher.clone();
+            let promo =
+                tokio::spawn(async move { low.send(Priority::Low, request("promo 2")).await });
+            tokio::task::yield_now().await;
+
+            let otp = tokio::time::timeout(
+                Duration::from_secs(5),
+                dispatcher.send(Priority::High, request("otp")),
+            )
+            .await;
+            assert!(matches!(otp, Ok(Ok(_))));
+            assert!(!promo.is_finished());
+            assert_eq!(*sent.lock().unwrap(), vec!["promo 1", "otp"]);
+            promo.abort();
+        });
+    }
+
+    #[test]
+    fn test_rate_limited_high_lane_does_not_block_lower_lanes() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let _mock = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_body(messaging_body(1))
+                .create();
+
+            let (client, sent) = recording_client(url);
+            let dispatcher = PriorityDispatcher::new(client)
+                .with_lane(
+                    Priority::High,
+                    LaneConfig::new(4).with_rate_limit(1, Duration::from_secs(60)),
+                )
+                .with_max_in_flight(2)
+                .spawn();
+
+            dispatcher
+                .send(Priority::High, request("otp 1"))
+                .await
+                .unwrap();
+            // The second OTP waits a minute for its slot; the promo behind it goes out meanwhile
+            let high = dispatcher.clone();
+            let otp =
+                tokio::spawn(async move { high.send(Priority::High, request("otp 2")).await });
+            tokio::task::yield_now().await;
+
+            let promo = tokio::time::timeout(
+                Duration::from_secs(5),
+                dispatcher.send(Priority::Low, request("promo")),
+            )
+            .await;
+            assert!(matches!(promo, Ok(Ok(_))));
+            assert!(!otp.is_finished());
+            assert_eq!(*sent.lock().unwrap(), vec!["otp 1", "promo"]);
+            otp.abort();
+        });
+    }
+
+    #[test]
+    fn test_send_after_runtime_shutdown_fails() {
+        let config = UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string());
+        let client = UjumbeSmsClient::new(config).unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let dispatcher = rt.block_on(async { PriorityDispatcher::new(client).spawn() });
+        drop(rt);
+
+        let result = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(dispatcher.send(Priority::High, MessageRequest::new()));
+        assert!(matches!(result, Err(UjumbeSmsError::TaskStopped(_))));
+    }
+}
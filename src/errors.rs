@@ -7,9 +7,20 @@ use std::fmt;
 #[derive(Debug)]
 pub enum UjumbeSmsError {
     NetworkError(reqwest::Error),
-    ApiError(String, String), // code, description
-    SerializationError(serde_json::Error),
+    ApiError(String, String),              // code, description
+    SerializationError(serde_json::Error), // also a success response whose body is not the expected JSON
     InvalidConfig(String),
+    OutsideSendingWindow(Option<chrono::DateTime<chrono::FixedOffset>>), // next open slot
+    StorageError(String),
+    BudgetExceeded(String),
+    CassetteError(String),
+    TransportError(crate::transport::BoxError), // raised by a custom service layer
+    Vetoed(String),                             // reason given by a request hook
+    InvalidSenderId(String),
+    InvalidRequest(Vec<crate::request_builder::RequestIssue>),
+    OtpCooldown(std::time::Duration), // time until another code may be sent
+    TaskStopped(String),              // a background task ended before answering
+    FailoverFailed(Option<Box<UjumbeSmsError>>, Box<UjumbeSmsError>), // primary (None if skipped), secondary
 }
 
 impl fmt::Display for UjumbeSmsError {
@@ -19,6 +30,63 @@ impl fmt::Display for UjumbeSmsError {
             UjumbeSmsError::ApiError(code, desc) => write!(f, "API error {code}: {desc}"),
             UjumbeSmsError::SerializationError(e) => write!(f, "Serialization error: {e}"),
             UjumbeSmsError::InvalidConfig(msg) => write!(f, "Invalid configuration: {msg}"),
+            UjumbeSmsError::OutsideSendingWindow(Some(next_open)) => {
+                write!(f, "Outside sending window: next open slot is {next_open}")
+            }
+            UjumbeSmsError::OutsideSendingWindow(None) => {
+                write!(f, "Outside sending window: no open slot configured")
+            }
+            UjumbeSmsError::StorageError(msg) => write!(f, "Storage error: {msg}"),
+            UjumbeSmsError::BudgetExceeded(msg) => write!(f, "Budget exceeded: {msg}"),
+            UjumbeSmsError::CassetteError(msg) => write!(f, "Cassette error: {msg}"),
+            UjumbeSmsError::TransportError(e) => write!(f, "Transport error: {e}"),
+            UjumbeSmsError::Vetoed(reason) => write!(f, "Request vetoed: {reason}"),
+            UjumbeSmsError::InvalidSenderId(msg) => write!(f, "Invalid sender ID: {msg}"),
+            UjumbeSmsError::InvalidRequest(issues) => {
+                let issues: Vec<String> = issues.iter().map(ToString::to_string).collect();
+                write!(f, "Invalid request: {}", issues.join("; "))
+            }
+            UjumbeSmsError::OtpCooldown(retry_after) => write!(
+                f,
+                "OTP resend cooldown: retry in {}s",
+                retry_after.as_secs_f64().ceil() as u64
+            ),
+            UjumbeSmsError::TaskStopped(msg) => write!(f, "Background task stopped: {msg}"),
+            UjumbeSmsError::FailoverFailed(Some(primary), secondary) => {
+                write!(
+                    f,
+                    "Failover failed: primary: {primary}; secondary: {secondary}"
+                )
+            }
+            UjumbeSmsError::FailoverFailed(None, secondary) => {
+                write!(
+                    f,
+                    "Failover failed: primary skipped; secondary: {secondary}"
+                )
+            }
+        }
+    }
+}
+
+impl UjumbeSmsError {
+    /// Short, stable name of the error variant, suitable as a metric label
+    pub fn kind(&self) -> &'static str {
+        match self {
+            UjumbeSmsError::NetworkError(_) => "network",
+            UjumbeSmsError::ApiError(_, _) => "api",
+            UjumbeSmsError::SerializationError(_) => "serialization",
+            UjumbeSmsError::InvalidConfig(_) => "invalid_config",
+            UjumbeSmsError::OutsideSendingWindow(_) => "outside_sending_window",
+            UjumbeSmsError::StorageError(_) => "storage",
+            UjumbeSmsError::BudgetExceeded(_) => "budget_exceeded",
+            UjumbeSmsError::CassetteError(_) => "cassette",
+            UjumbeSmsError::TransportError(_) => "transport",
+            UjumbeSmsError::Vetoed(_) => "vetoed",
+            UjumbeSmsError::InvalidSenderId(_) => "invalid_sender_id",
+            UjumbeSmsError::InvalidRequest(_) => "invalid_request",
+            UjumbeSmsError::OtpCooldown(_) => "otp_cooldown",
+            UjumbeSmsError::TaskStopped(_) => "task_stopped",
+            UjumbeSmsError::FailoverFailed(_, _) => "failover_failed",
         }
     }
 }
@@ -36,3 +104,16 @@ impl From<serde_json::Error> for UjumbeSmsError {
         UjumbeSmsError::SerializationError(error)
     }
 }
+
+impl From<std::io::Error> for UjumbeSmsError {
+    fn from(error: std::io::Error) -> Self {
+        UjumbeSmsError::StorageError(error.to_string())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for UjumbeSmsError {
+    fn from(error: rusqlite::Error) -> Self {
+        UjumbeSmsError::StorageError(error.to_string())
+    }
+}
@@ -0,0 +1,392 @@
+use crate::client::UjumbeSmsClient;
+use crate::errors::UjumbeSmsError;
+use crate::models::MessageSent;
+use crate::phone::mask_number;
+use chrono::NaiveDateTime;
+use std::io::Write;
+
+/// Format of `MessageSent.created_at` and the other history timestamps
+pub const HISTORY_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// `ExportFormat` selects the writer used by `HistoryExporter`. Its variants depend on the
+/// enabled features, so matches need a wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExportFormat {
+    /// Comma separated values with a header row
+    #[cfg(feature = "csv")]
+    Csv,
+    /// One JSON object per line
+    JsonLines,
+}
+
+/// `Column` is an exportable field of `MessageSent`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Column {
+    Id,
+    RequestId,
+    Number,
+    Message,
+    UserId,
+    SenderId,
+    TransactionId,
+    MessageCount,
+    Status,
+    Flag,
+    CreatedAt,
+    UpdatedAt,
+    ScheduledDate,
+}
+
+impl Column {
+    /// Every column, in `MessageSent` field order
+    pub const ALL: [Column; 13] = [
+        Column::Id,
+        Column::RequestId,
+        Column::Number,
+        Column::Message,
+        Column::UserId,
+        Column::SenderId,
+        Column::TransactionId,
+        Column::MessageCount,
+        Column::Status,
+        Column::Flag,
+        Column::CreatedAt,
+        Column::UpdatedAt,
+        Column::ScheduledDate,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Column::Id => "id",
+            Column::RequestId => "request_id",
+            Column::Number => "number",
+            Column::Message => "message",
+            Column::UserId => "user_id",
+            Column::SenderId => "sender_id",
+            Column::TransactionId => "transaction_id",
+            Column::MessageCount => "message_count",
+            Column::Status => "status",
+            Column::Flag => "flag",
+            Column::CreatedAt => "created_at",
+            Column::UpdatedAt => "updated_at",
+            Column::ScheduledDate => "scheduled_date",
+        }
+    }
+
+    fn value(&self, message: &MessageSent, mask: bool) -> serde_json::Value {
+        use serde_json::Value;
+        match self {
+            Column::Id => Value::from(message.id),
+            Column::RequestId => Value::from(message.request_id),
+            Column::Number if mask => Value::from(mask_number(&message.number)),
+            Column::Number => Value::from(message.number.as_str()),
+            Column::Message => Value::from(message.message.as_str()),
+            Column::UserId => Value::from(message.user_id),
+            Column::SenderId => Value::from(message.sender_id.as_str()),
+            Column::TransactionId => Value::from(message.transaction_id.as_str()),
+            Column::MessageCount => Value::from(message.message_count),
+            Column::Status => Value::from(message.status.as_str()),
+            Column::Flag => Value::from(message.flag.as_str()),
+            Column::CreatedAt => Value::from(message.created_at.as_str()),
+            Column::UpdatedAt => Value::from(message.updated_at.as_str()),
+            Column::ScheduledDate => Value::from(message.scheduled_date.as_str()),
+        }
+    }
+}
+
+impl std::str::FromStr for Column {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Column::ALL
+            .into_iter()
+            .find(|column| column.as_str() == s)
+            .ok_or(())
+    }
+}
+
+/// `ExportSummary` describes a finished export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub pages: u32,
+    pub rows_written: usize,
+    /// Rows outside the date range
+    pub rows_skipped: usize,
+}
+
+/// `HistoryExporter` streams the full message history, one page at a time, into a CSV or
+/// JSON Lines writer. Only the current page is held in memory.
+pub struct HistoryExporter<'a> {
+    client: &'a UjumbeSmsClient,
+    columns: Vec<Column>,
+    mask_numbers: bool,
+    since: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+}
+
+impl<'a> HistoryExporter<'a> {
+    /// Exports every column, unmasked and without date bounds
+    pub fn new(client: &'a UjumbeSmsClient) -> Self {
+        HistoryExporter {
+            client,
+            columns: Column::ALL.to_vec(),
+            mask_numbers: false,
+            since: None,
+            until: None,
+        }
+    }
+
+    pub fn with_columns(mut self, columns: impl IntoIterator<Item = Column>) -> Self {
+        self.columns = columns.into_iter().collect();
+        self
+    }
+
+    /// Replaces phone numbers with their masked form, e.g. `254712****78`
+    pub fn with_masked_numbers(mut self, mask: bool) -> Self {
+        self.mask_numbers = mask;
+        self
+    }
+
+    /// Only exports rows created in `[since, until)`, in the API's Africa/Nairobi local time
+    pub fn with_date_range(
+        mut self,
+        since: Option<NaiveDateTime>,
+        until: Option<NaiveDateTime>,
+    ) -> Self {
+        self.since = since;
+        self.until = until;
+        self
+    }
+
+    /// Writes the history to `writer`. History pages are newest first, so paging stops
+    /// as soon as a page ends before `since`.
+    pub async fn export<W: Write>(
+        &self,
+        format: ExportFormat,
+        writer: W,
+    ) -> Result<ExportSummary, UjumbeSmsError> {
+        let mut sink = Sink::new(format, writer, &self.columns)?;
+        let mut summary = ExportSummary::default();
+        let mut page = 1;
+
+        loop {
+            let history = self.client.get_messages_history_page(page).await?;
+            summary.pages += 1;
+
+            let mut reached_start = false;
+            for message in &history.items.data {
+                let created =
+                    NaiveDateTime::parse_from_str(&message.created_at, HISTORY_DATE_FORMAT).ok();
+                if self.in_range(created) {
+                    sink.write(message, &self.columns, self.mask_numbers)?;
+                    summary.rows_written += 1;
+                } else {
+                    summary.rows_skipped += 1;
+                }
+                reached_start |= matches!((created, self.since), (Some(c), Some(s)) if c < s);
+            }
+
+            if reached_start
+                || history.items.data.is_empty()
+                || history.items.current_page >= history.items.last_page
+            {
+                break;
+            }
+            page += 1;
+        }
+
+        sink.finish()?;
+        Ok(summary)
+    }
+
+    fn in_range(&self, created: Option<NaiveDateTime>) -> bool {
+        match created {
+            Some(created) => {
+                self.since.is_none_or(|since| created >= since)
+                    && self.until.is_none_or(|until| created < until)
+            }
+            None => self.since.is_none() && self.until.is_none(),
+        }
+    }
+}
+
+enum Sink<W: Write> {
+    #[cfg(feature = "csv")]
+    Csv(Box<csv::Writer<W>>),
+    JsonLines(W),
+}
+
+impl<W: Write> Sink<W> {
+    // Only the CSV header needs the columns up front
+    #[cfg_attr(not(feature = "csv"), allow(unused_variables))]
+    fn new(format: ExportFormat, writer: W, columns: &[Column]) -> Result<Self, UjumbeSmsError> {
+        match format {
+            #[cfg(feature = "csv")]
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(writer);
+                writer
+                    .write_record(columns.iter().map(Column::as_str))
+                    .map_err(csv_error)?;
+                Ok(Sink::Csv(Box::new(writer)))
+            }
+            ExportFormat::JsonLines => Ok(Sink::JsonLines(writer)),
+        }
+    }
+
+    fn write(
+        &mut self,
+        message: &MessageSent,
+        columns: &[Column],
+        mask: bool,
+    ) -> Result<(), UjumbeSmsError> {
+        match self {
+            #[cfg(feature = "csv")]
+            Sink::Csv(writer) => writer
+                .write_record(
+                    columns
+                        .iter()
+                        .map(|column| match column.value(message, mask) {
+                            serde_json::Value::String(s) => s,
+                            other => other.to_string(),
+                        }),
+                )
+                .map_err(csv_error),
+            Sink::JsonLines(writer) => {
+                let row: serde_json::Map<String, serde_json::Value> = columns
+                    .iter()
+                    .map(|column| (column.as_str().to_string(), column.value(message, mask)))
+                    .collect();
+                serde_json::to_writer(&mut *writer, &row)?;
+                Ok(writer.write_all(b"\n")?)
+            }
+        }
+    }
+
+    fn finish(self) -> Result<(), UjumbeSmsError> {
+        match self {
+            #[cfg(feature = "csv")]
+            Sink::Csv(mut writer) => Ok(writer.flush()?),
+            Sink::JsonLines(mut writer) => Ok(writer.flush()?),
+        }
+    }
+}
+
+#[cfg(feature = "csv")]
+fn csv_error(error: csv::Error) -> UjumbeSmsError {
+    UjumbeSmsError::StorageError(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{history_page, sent};
+    use crate::UjumbeSmsConfig;
+    use chrono::NaiveDate;
+    use mockito::{Matcher, Server};
+
+    fn page(page: i32, last_page: i32, rows: &[(i64, &str)]) -> String {
+        let rows = rows.iter().map(|(id, created_at)| {
+            sent(*id)
+                .request_id(500)
+                .message("Hello, \"world\"")
+                .created_at(created_at)
+        });
+        serde_json::to_string(&history_page(page, last_page, rows)).unwrap()
+    }
+
+    #[test]
+    fn test_export_streams_pages_within_date_range() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let pages = [
+                page(
+                    1,
+                    3,
+                    &[(6, "2025-08-01 00:00:00"), (5, "2025-07-31 23:59:59")],
+                ),
+                page(
+                    2,
+                    3,
+                    &[(4, "2025-07-15 12:00:00"), (3, "2025-06-30 23:59:59")],
+                ),
+                page(
+                    3,
+                    3,
+                    &[(2, "2025-06-01 10:00:00"), (1, "2025-05-01 10:00:00")],
+                ),
+            ];
+            let exports = if cfg!(feature = "csv") { 2 } else { 1 };
+            let mocks: Vec<_> = pages
+                .iter()
+                .enumerate()
+                .map(|(i, body)| {
+                    server
+                        .mock("POST", "/api/messages")
+                        .match_query(Matcher::UrlEncoded("page".into(), (i + 1).to_string()))
+                        .with_status(200)
+                        .with_body(body)
+                        // Every export stops after page 2, which ends before July
+                        .expect(if i < 2 { exports } else { 0 })
+                        .create()
+                })
+                .collect();
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let client = UjumbeSmsClient::new(config).unwrap();
+            let month_start = |month: u32| {
+                NaiveDate::from_ymd_opt(2025, month, 1)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap()
+            };
+            let exporter = HistoryExporter::new(&client)
+                .with_columns([Column::Id, Column::Number, Column::Message])
+                .with_masked_numbers(true)
+                .with_date_range(Some(month_start(7)), Some(month_start(8)));
+
+            #[cfg(feature = "csv")]
+            {
+                let mut csv = Vec::new();
+                exporter.export(ExportFormat::Csv, &mut csv).await.unwrap();
+                assert_eq!(
+                    String::from_utf8(csv).unwrap(),
+                    "id,number,message\n\
+                     5,254712****78,\"Hello, \"\"world\"\"\"\n\
+                     4,254712****78,\"Hello, \"\"world\"\"\"\n"
+                );
+            }
+
+            let mut jsonl = Vec::new();
+            let summary = exporter
+                .export(ExportFormat::JsonLines, &mut jsonl)
+                .await
+                .unwrap();
+            assert_eq!(
+                summary,
+                ExportSummary {
+                    pages: 2,
+                    rows_written: 2,
+                    rows_skipped: 2
+                }
+            );
+            let lines: Vec<serde_json::Value> = String::from_utf8(jsonl)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            assert_eq!(lines.len(), 2);
+            assert_eq!(lines[0]["id"], 5);
+            assert_eq!(lines[0]["number"], "254712****78");
+            assert!(lines[0].get("status").is_none());
+
+            for mock in &mocks {
+                mock.assert();
+            }
+        });
+    }
+}
@@ -0,0 +1,448 @@
+use crate::client::UjumbeSmsClient;
+use crate::errors::UjumbeSmsError;
+use crate::models::{MessageRequest, MessagingApiResponse};
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Default number of consecutive primary failures that open the circuit
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+/// Default time the circuit stays open before the primary is tried again
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+/// HTTP statuses that fail over by default: rate limiting and server errors
+pub const DEFAULT_FAILOVER_STATUSES: [u16; 5] = [429, 500, 502, 503, 504];
+
+/// Future returned by `SmsProvider::send_messages`
+pub type SendFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<MessagingApiResponse, UjumbeSmsError>> + Send + 'a>>;
+
+/// `SmsProvider` is anything that can send a `MessageRequest`. Implement it for a secondary
+/// gateway, translating its response into a `MessagingApiResponse`.
+pub trait SmsProvider: Send + Sync {
+    /// Name recorded in `FailoverDelivery::provider`
+    fn name(&self) -> &str;
+
+    fn send_messages(&self, request: MessageRequest) -> SendFuture<'_>;
+}
+
+impl SmsProvider for UjumbeSmsClient {
+    fn name(&self) -> &str {
+        "ujumbesms"
+    }
+
+    fn send_messages(&self, request: MessageRequest) -> SendFuture<'_> {
+        Box::pin(UjumbeSmsClient::send_messages(self, request))
+    }
+}
+
+/// Delivers through `FailoverProvider::send`, so a failover chain can itself be a secondary
+impl SmsProvider for FailoverProvider {
+    fn name(&self) -> &str {
+        "failover"
+    }
+
+    fn send_messages(&self, request: MessageRequest) -> SendFuture<'_> {
+        Box::pin(async move { Ok(self.send(request).await?.response) })
+    }
+}
+
+/// `CircuitState` is the state of a `FailoverProvider`'s circuit breaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests go to the primary
+    Closed,
+    /// The primary is skipped until the cooldown ends
+    Open,
+    /// The cooldown ended; one request is trying the primary
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct Circuit {
+    state: CircuitState,
+    failures: u32,
+    opened_at: Option<Instant>,
+}
+
+/// `FailoverDelivery` is a successful send and the provider that delivered it
+#[derive(Debug)]
+pub struct FailoverDelivery {
+    pub provider: String,
+    pub response: MessagingApiResponse,
+    /// The secondary delivered the request
+    pub failed_over: bool,
+    /// The primary's error, or `None` if it delivered or was skipped because the circuit was open
+    pub primary_error: Option<UjumbeSmsError>,
+}
+
+/// `FailoverProvider` sends through a primary `UjumbeSmsClient` and falls back to a secondary
+/// `SmsProvider` on network and transport errors, or on the configured HTTP statuses. Other
+/// errors, e.g. a rejected request, are returned without failing over. After
+/// `failure_threshold` consecutive failures the circuit opens and requests go straight to the
+/// secondary until the cooldown ends and a single request tries the primary again. When the
+/// secondary fails too, the error is `UjumbeSmsError::FailoverFailed` with both errors.
+pub struct FailoverProvider {
+    primary: UjumbeSmsClient,
+    secondary: Box<dyn SmsProvider>,
+    failure_threshold: u32,
+    cooldown: Duration,
+    failover_statuses: BTreeSet<u16>,
+    circuit: Mutex<Circuit>,
+}
+
+impl FailoverProvider {
+    pub fn new(primary: UjumbeSmsClient, secondary: impl SmsProvider + 'static) -> Self {
+        FailoverProvider {
+            primary,
+            secondary: Box::new(secondary),
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cooldown: DEFAULT_COOLDOWN,
+            failover_statuses: DEFAULT_FAILOVER_STATUSES.into_iter().collect(),
+            circuit: Mutex::new(Circuit {
+                state: CircuitState::Closed,
+                failures: 0,
+                opened_at: None,
+            }),
+        }
+    }
+
+    /// Consecutive primary failures that open the circuit (zero is treated as one)
+    pub fn with_failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Replaces the HTTP statuses that fail over
+    pub fn with_failover_statuses(mut self, statuses: impl IntoIterator<Item = u16>) -> Self {
+        self.failover_statuses = statuses.into_iter().collect();
+        self
+    }
+
+    pub fn circuit_state(&self) -> CircuitState {
+        self.circuit.lock().expect("circuit lock poisoned").state
+    }
+
+    /// Sends `request` through the primary, or the secondary if the primary is unavailable
+    pub async fn send(&self, request: MessageRequest) -> Result<FailoverDelivery, UjumbeSmsError> {
+        if !self.try_primary() {
+            return self.send_secondary(request, None).await;
+        }
+
+        match self.primary.send_messages(request.clone()).await {
+            Ok(response) => {
+                self.record(true);
+                Ok(FailoverDelivery {
+                    provider: SmsProvider::name(&self.primary).to_string(),
+                    response,
+                    failed_over: false,
+                    primary_error: None,
+                })
+            }
+            Err(error) if self.should_fail_over(&error) => {
+                self.record(false);
+                self.send_secondary(request, Some(error)).await
+            }
+            Err(error) => {
+                // The primary answered, so the outage is over
+                self.record(true);
+                Err(error)
+            }
+        }
+    }
+
+    /// Sends through the secondary; if it fails too, the error keeps the primary's
+    async fn send_secondary(
+        &self,
+        request: MessageRequest,
+        primary_error: Option<UjumbeSmsError>,
+    ) -> Result<FailoverDelivery, UjumbeSmsError> {
+        let response = match self.secondary.send_messages(request).await {
+            Ok(response) => response,
+            Err(error) => {
+                return Err(UjumbeSmsError::FailoverFailed(
+                    primary_error.map(Box::new),
+                    Box::new(error),
+                ))
+            }
+        };
+        Ok(FailoverDelivery {
+            provider: self.secondary.name().to_string(),
+            response,
+            failed_over: true,
+            primary_error,
+        })
+    }
+
+    fn should_fail_over(&self, error: &UjumbeSmsError) -> bool {
+        match error {
+            UjumbeSmsError::NetworkError(_) | UjumbeSmsError::TransportError(_) => true,
+            // The code is the HTTP status line, e.g. "503 Service Unavailable"
+            UjumbeSmsError::ApiError(code, _) => code
+                .split_whitespace()
+                .next()
+                .and_then(|status| status.parse().ok())
+                .is_some_and(|status| self.failover_statuses.contains(&status)),
+            _ => false,
+        }
+    }
+
+    /// Whether this request may use the primary, moving an expired open circuit to half-open
+    fn try_primary(&self) -> bool {
+        let mut circuit = self.circuit.lock().expect("circuit lock poisoned");
+        if circuit.state == CircuitState::Closed {
+            return true;
+        }
+        // A half-open trial that never finished (e.g. its future was dropped) is retried
+        // after another cooldown
+        let cooled_down = circuit
+            .opened_at
+            .is_some_and(|opened_at| opened_at.elapsed() >= self.cooldown);
+        if cooled_down {
+            circuit.state = CircuitState::HalfOpen;
+            circuit.opened_at = Some(Instant::now());
+        }
+        cooled_down
+    }
+
+    fn record(&self, success: bool) {
+        let mut circuit = self.circuit.lock().expect("circuit lock poisoned");
+        if success {
+            circuit.state = CircuitState::Closed;
+            circuit.failures = 0;
+            circuit.opened_at = None;
+            return;
+        }
+
+        circuit.failures += 1;
+        if circuit.state == CircuitState::HalfOpen || circuit.failures >= self.failure_threshold {
+            circuit.state = CircuitState::Open;
+            circuit.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::StatusInfo;
+    use crate::test_support::messaging_body;
+    use crate::UjumbeSmsConfig;
+    use mockito::{Matcher, Server};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Secondary provider that accepts everything and counts its calls
+    struct Backup(Arc<AtomicUsize>);
+
+    impl SmsProvider for Backup {
+        fn name(&self) -> &str {
+            "backup"
+        }
+
+        fn send_messages(&self, request: MessageRequest) -> SendFuture<'_> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                Ok(MessagingApiResponse {
+                    status: StatusInfo {
+                        code: "200".to_string(),
+                        r#type: "success".to_string(),
+                        description: format!("sent to {}", request.recipient_count()),
+                    },
+                    meta: None,
+                })
+            })
+        }
+    }
+
+    /// Secondary provider that is always down
+    struct Down;
+
+    impl SmsProvider for Down {
+        fn name(&self) -> &str {
+            "down"
+        }
+
+        fn send_messages(&self, _request: MessageRequest) -> SendFuture<'_> {
+            Box::pin(async {
+                Err(UjumbeSmsError::ApiError(
+                    "503 Service Unavailable".to_string(),
+                    "down".to_string(),
+                ))
+            })
+        }
+    }
+
+    fn request(message: &str) -> MessageRequest {
+        let mut request = MessageRequest::new();
+        request.add_message_bag(
+            "254712345678".to_string(),
+            message.to_string(),
+            "UjumbeSMS".to_string(),
+        );
+        request
+    }
+
+    #[test]
+    fn test_fails_over_and_opens_circuit() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let outage = server
+                .mock("POST", "/api/messaging")
+                .match_body(Matcher::Regex(r#""message":"Alert""#.to_string()))
+                .with_status(503)
+                .with_body("Service Unavailable")
+                .expect(2)
+                .create();
+            let rejected = server
+                .mock("POST", "/api/messaging")
+                .match_body(Matcher::Regex(r#""message":"Bad""#.to_string()))
+                .with_status(400)
+                .with_body("Bad Request")
+                .create();
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let calls = Arc::new(AtomicUsize::new(0));
+            let provider =
+                FailoverProvider::new(UjumbeSmsClient::new(config).unwrap(), Backup(calls.clone()))
+                    .with_failure_threshold(2)
+                    .with_cooldown(Duration::from_secs(60));
+
+            // A client error is returned as is and does not count as an outage
+            assert!(matches!(
+                provider.send(request("Bad")).await,
+                Err(UjumbeSmsError::ApiError(code, _)) if code.starts_with("400")
+            ));
+            rejected.assert();
+            assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+            for _ in 0..2 {
+                let delivery = provider.send(request("Alert")).await.unwrap();
+                assert_eq!(delivery.provider, "backup");
+                assert!(matches!(
+                    delivery.primary_error,
+                    Some(UjumbeSmsError::ApiError(_, _))
+                ));
+            }
+            assert_eq!(provider.circuit_state(), CircuitState::Open);
+
+            // With the circuit open the primary is skipped entirely
+            let delivery = provider.send(request("Alert")).await.unwrap();
+            assert_eq!(delivery.provider, "backup");
+            assert!(delivery.primary_error.is_none() && delivery.failed_over);
+            outage.assert();
+            assert_eq!(calls.load(Ordering::SeqCst), 3);
+        });
+    }
+
+    #[test]
+    fn test_half_open_circuit_closes_on_success() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let _mock = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_body(messaging_body(1))
+                .create();
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let provider = FailoverProvider::new(
+                UjumbeSmsClient::new(config).unwrap(),
+                Backup(Arc::default()),
+            )
+            .with_cooldown(Duration::ZERO);
+            provider.record(false);
+            provider.record(false);
+            provider.record(false);
+            assert_eq!(provider.circuit_state(), CircuitState::Open);
+
+            let delivery = provider.send(request("Hello")).await.unwrap();
+            assert_eq!(delivery.provider, "ujumbesms");
+            assert!(!delivery.failed_over);
+            assert_eq!(provider.circuit_state(), CircuitState::Closed);
+        });
+    }
+
+    #[test]
+    fn test_both_failures_are_reported() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let _outage = server
+                .mock("POST", "/api/messaging")
+                .with_status(502)
+                .with_body("Bad Gateway")
+                .create();
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let provider = FailoverProvider::new(UjumbeSmsClient::new(config).unwrap(), Down)
+                .with_failure_threshold(1);
+
+            let error = provider.send(request("Alert")).await.unwrap_err();
+            assert!(matches!(
+                &error,
+                UjumbeSmsError::FailoverFailed(Some(primary), secondary)
+                    if matches!(&**primary, UjumbeSmsError::ApiError(code, _) if code.starts_with("502"))
+                        && secondary.kind() == "api"
+            ));
+            assert_eq!(error.kind(), "failover_failed");
+
+            // With the circuit open there is no primary error to report
+            assert!(matches!(
+                provider.send(request("Alert")).await,
+                Err(UjumbeSmsError::FailoverFailed(None, _))
+            ));
+        });
+    }
+
+    #[test]
+    fn test_failover_provider_is_a_provider() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let _outage = server
+                .mock("POST", "/api/messaging")
+                .with_status(503)
+                .with_body("Service Unavailable")
+                .expect(2)
+                .create();
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let calls = Arc::new(AtomicUsize::new(0));
+            let inner = FailoverProvider::new(
+                UjumbeSmsClient::new(config.clone()).unwrap(),
+                Backup(calls.clone()),
+            );
+            let outer = FailoverProvider::new(UjumbeSmsClient::new(config).unwrap(), inner);
+
+            let delivery = outer.send(request("Alert")).await.unwrap();
+            assert_eq!(delivery.provider, "failover");
+            assert_eq!(delivery.response.status.description, "sent to 1");
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+        });
+    }
+}
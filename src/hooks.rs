@@ -0,0 +1,142 @@
+use crate::client::ApiEndpoint;
+use crate::errors::UjumbeSmsError;
+use crate::models::{BalanceApiResponse, MessageHistoryApiResponse, MessagingApiResponse};
+
+/// `HookDecision` is returned by `RequestHook::before_request`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookDecision {
+    Proceed,
+    /// Stop the call before it is sent; the caller receives `UjumbeSmsError::Vetoed` with this reason
+    Veto(String),
+}
+
+/// `HookResponse` is the parsed response of a call, whichever endpoint it went to
+#[derive(Debug, Clone, Copy)]
+pub enum HookResponse<'a> {
+    Messaging(&'a MessagingApiResponse),
+    Balance(&'a BalanceApiResponse),
+    MessageHistory(&'a MessageHistoryApiResponse),
+}
+
+/// `RequestHook` observes, and can veto, every call made by a `UjumbeSmsClient`.
+/// `request` is the serialized JSON body, or `None` for endpoints that take no body.
+/// Hooks run in registration order; the first veto stops the call and skips the remaining `before_request` hooks.
+pub trait RequestHook: Send + Sync {
+    fn before_request(&self, _endpoint: &ApiEndpoint, _request: Option<&str>) -> HookDecision {
+        HookDecision::Proceed
+    }
+
+    /// Called once the call has finished, including calls that were vetoed or failed
+    fn after_response(
+        &self,
+        _endpoint: &ApiEndpoint,
+        _request: Option<&str>,
+        _outcome: Result<HookResponse<'_>, &UjumbeSmsError>,
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::messaging_body;
+    use crate::{UjumbeSmsClient, UjumbeSmsConfig};
+    use mockito::Server;
+    use std::sync::{Arc, Mutex};
+
+    /// Records every hook invocation as a line of text
+    #[derive(Default)]
+    struct AuditLog(Mutex<Vec<String>>);
+
+    impl RequestHook for Arc<AuditLog> {
+        fn before_request(&self, endpoint: &ApiEndpoint, request: Option<&str>) -> HookDecision {
+            self.0.lock().unwrap().push(format!(
+                "before {} {}",
+                endpoint.as_str(),
+                request.unwrap_or("-")
+            ));
+            HookDecision::Proceed
+        }
+
+        fn after_response(
+            &self,
+            endpoint: &ApiEndpoint,
+            _request: Option<&str>,
+            outcome: Result<HookResponse<'_>, &UjumbeSmsError>,
+        ) {
+            let outcome = match outcome {
+                Ok(HookResponse::Messaging(response)) => {
+                    format!("messaging {}", response.status.code)
+                }
+                Ok(HookResponse::Balance(response)) => format!("balance {}", response.status.code),
+                Ok(HookResponse::MessageHistory(_)) => "history".to_string(),
+                Err(error) => format!("error {}", error.kind()),
+            };
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("after {} {outcome}", endpoint.as_str()));
+        }
+    }
+
+    /// Vetoes any message containing a blocked word
+    struct ContentFilter(&'static str);
+
+    impl RequestHook for ContentFilter {
+        fn before_request(&self, _endpoint: &ApiEndpoint, request: Option<&str>) -> HookDecision {
+            match request {
+                Some(body) if body.contains(self.0) => {
+                    HookDecision::Veto(format!("message contains \"{}\"", self.0))
+                }
+                _ => HookDecision::Proceed,
+            }
+        }
+    }
+
+    #[test]
+    fn test_hooks_audit_and_veto() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let sent = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_body(messaging_body(1))
+                .expect(1)
+                .create();
+
+            let audit = Arc::new(AuditLog::default());
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let client = UjumbeSmsClient::builder(config)
+                .with_hook(audit.clone())
+                .with_hook(ContentFilter("casino"))
+                .build()
+                .unwrap();
+
+            client
+                .send_single_message("254712345678", "Your code is 1234", "UjumbeSMS")
+                .await
+                .unwrap();
+
+            match client
+                .send_single_message("254712345678", "Visit our casino", "UjumbeSMS")
+                .await
+            {
+                Err(UjumbeSmsError::Vetoed(reason)) => assert!(reason.contains("casino")),
+                other => panic!("Expected Vetoed, got {other:?}"),
+            }
+            sent.assert();
+
+            let log = audit.0.lock().unwrap();
+            assert_eq!(log.len(), 4);
+            assert!(log[0].starts_with("before /api/messaging {\"data\""));
+            assert!(log[0].contains("Your code is 1234"));
+            assert_eq!(log[1], "after /api/messaging messaging 1008");
+            assert_eq!(log[3], "after /api/messaging error vetoed");
+        });
+    }
+}
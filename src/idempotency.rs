@@ -0,0 +1,289 @@
+use crate::client::UjumbeSmsClient;
+use crate::errors::UjumbeSmsError;
+use crate::models::{MessageRequest, MessagingApiResponse};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Default period during which a repeated send is treated as a duplicate
+pub const DEFAULT_IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// Derives an idempotency key from the numbers, message and sender of every bag in `request`.
+/// The key is the hex encoded SHA-256 of the bag fields, so it is stable across processes.
+pub fn derive_key(request: &MessageRequest) -> String {
+    let mut hasher = Sha256::new();
+    for container in &request.data {
+        let bag = &container.message_bag;
+        for field in [&bag.numbers, &bag.message, &bag.sender] {
+            hasher.update(field.as_bytes());
+            hasher.update([0u8]);
+        }
+        hasher.update([0xffu8]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// `IdempotencyStore` remembers the response of each completed send by key
+pub trait IdempotencyStore: Send + Sync {
+    /// Returns the stored response for `key` unless it has expired
+    fn get(&self, key: &str) -> Result<Option<MessagingApiResponse>, UjumbeSmsError>;
+
+    /// Stores `response` under `key` for `ttl`
+    fn insert(
+        &self,
+        key: &str,
+        response: &MessagingApiResponse,
+        ttl: Duration,
+    ) -> Result<(), UjumbeSmsError>;
+}
+
+/// `InMemoryIdempotencyStore` keeps responses in process memory; expired keys are purged on insert
+#[derive(Debug, Default)]
+pub struct InMemoryIdempotencyStore {
+    entries: Mutex<HashMap<String, (Instant, MessagingApiResponse)>>,
+}
+
+impl InMemoryIdempotencyStore {
+    pub fn new() -> Self {
+        InMemoryIdempotencyStore::default()
+    }
+}
+
+impl IdempotencyStore for InMemoryIdempotencyStore {
+    fn get(&self, key: &str) -> Result<Option<MessagingApiResponse>, UjumbeSmsError> {
+        let entries = self
+            .entries
+            .lock()
+            .expect("idempotency store lock poisoned");
+        Ok(entries
+            .get(key)
+            .filter(|(expires_at, _)| *expires_at > Instant::now())
+            .map(|(_, response)| response.clone()))
+    }
+
+    fn insert(
+        &self,
+        key: &str,
+        response: &MessagingApiResponse,
+        ttl: Duration,
+    ) -> Result<(), UjumbeSmsError> {
+        let mut entries = self
+            .entries
+            .lock()
+            .expect("idempotency store lock poisoned");
+        let now = Instant::now();
+        entries.retain(|_, (expires_at, _)| *expires_at > now);
+        entries.insert(key.to_string(), (now + ttl, response.clone()));
+        Ok(())
+    }
+}
+
+/// `IdempotentClient` wraps `UjumbeSmsClient` so that repeated sends with the same key
+/// inside the window return the original `MessagingApiResponse` instead of sending again.
+/// Concurrent sends with the same key are serialised, so only one reaches the API.
+pub struct IdempotentClient {
+    client: UjumbeSmsClient,
+    store: Box<dyn IdempotencyStore>,
+    window: Duration,
+    in_flight: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl IdempotentClient {
+    /// Creates an idempotent client backed by an `InMemoryIdempotencyStore`
+    pub fn new(client: UjumbeSmsClient) -> Self {
+        IdempotentClient {
+            client,
+            store: Box::new(InMemoryIdempotencyStore::new()),
+            window: DEFAULT_IDEMPOTENCY_WINDOW,
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_store(mut self, store: impl IdempotencyStore + 'static) -> Self {
+        self.store = Box::new(store);
+        self
+    }
+
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn client(&self) -> &UjumbeSmsClient {
+        &self.client
+    }
+
+    /// Sends `request` once per key. When `key` is `None` it is derived with `derive_key`.
+    /// Only successful responses are remembered, so failed sends can be retried.
+    pub async fn send_messages(
+        &self,
+        request: MessageRequest,
+        key: Option<&str>,
+    ) -> Result<MessagingApiResponse, UjumbeSmsError> {
+        let key = key.map_or_else(|| derive_key(&request), str::to_string);
+
+        let lock = {
+            let mut in_flight = self.in_flight.lock().expect("in-flight lock poisoned");
+            in_flight.entry(key.clone()).or_default().clone()
+        };
+        let guard = lock.lock().await;
+
+        let result = match self.store.get(&key) {
+            Ok(Some(response)) => Ok(response),
+            Ok(None) => match self.client.send_messages(request).await {
+                Ok(response) => self
+                    .store
+                    .insert(&key, &response, self.window)
+                    .map(|_| response),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+
+        drop(guard);
+        let mut in_flight = self.in_flight.lock().expect("in-flight lock poisoned");
+        // The map and this function hold the only references once no other caller waits on the key
+        if Arc::strong_count(&lock) <= 2 {
+            in_flight.remove(&key);
+        }
+
+        result
+    }
+
+    /// Convenience method to send a single message with duplicate protection
+    pub async fn send_single_message(
+        &self,
+        numbers: &str,
+        message: &str,
+        sender: &str,
+        key: Option<&str>,
+    ) -> Result<MessagingApiResponse, UjumbeSmsError> {
+        let mut request = MessageRequest::new();
+        request.add_message_bag(numbers.to_string(), message.to_string(), sender.to_string());
+        self.send_messages(request, key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::messaging_body;
+    use crate::UjumbeSmsConfig;
+    use mockito::Server;
+
+    fn request(numbers: &str, message: &str) -> MessageRequest {
+        let mut request = MessageRequest::new();
+        request.add_message_bag(
+            numbers.to_string(),
+            message.to_string(),
+            "UjumbeSMS".to_string(),
+        );
+        request
+    }
+
+    #[test]
+    fn test_derive_key_is_stable_and_field_sensitive() {
+        let key = derive_key(&request("254712345678", "Hello"));
+        assert_eq!(key.len(), 64);
+        assert_eq!(key, derive_key(&request("254712345678", "Hello")));
+        assert_ne!(key, derive_key(&request("254712345678", "Hello!")));
+        // Field boundaries are part of the hash
+        assert_ne!(
+            derive_key(&request("2547123456781", "Hello")),
+            derive_key(&request("254712345678", "1Hello"))
+        );
+    }
+
+    fn client(server: &Server) -> IdempotentClient {
+        let config = UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+            .with_base_url(server.url());
+        IdempotentClient::new(UjumbeSmsClient::new(config).unwrap())
+    }
+
+    #[test]
+    fn test_duplicate_sends_hit_the_api_once() {
+        let mut server = Server::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let mock = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_header("content-type", "application/json")
+                .with_body(messaging_body(1))
+                .expect(1)
+                .create();
+            let client = client(&server);
+
+            let first = client
+                .send_single_message("254712345678", "Your order shipped", "UjumbeSMS", None)
+                .await
+                .unwrap();
+            let second = client
+                .send_single_message("254712345678", "Your order shipped", "UjumbeSMS", None)
+                .await
+                .unwrap();
+            assert_eq!(first.status.code, second.status.code);
+
+            mock.assert();
+        });
+    }
+
+    #[test]
+    fn test_explicit_key_replaces_the_content_hash() {
+        let mut server = Server::new();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let mock = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_header("content-type", "application/json")
+                .with_body(messaging_body(1))
+                .expect(2)
+                .create();
+            let client = client(&server);
+
+            // Same content under a different key is sent again
+            client
+                .send_single_message("254712345678", "Your order shipped", "UjumbeSMS", None)
+                .await
+                .unwrap();
+            client
+                .send_single_message(
+                    "254712345678",
+                    "Your order shipped",
+                    "UjumbeSMS",
+                    Some("job-42"),
+                )
+                .await
+                .unwrap();
+            // Different content under the same key is not
+            client
+                .send_single_message("254712345678", "Retry", "UjumbeSMS", Some("job-42"))
+                .await
+                .unwrap();
+
+            mock.assert();
+        });
+    }
+
+    #[test]
+    fn test_in_memory_store_expires_entries() {
+        let store = InMemoryIdempotencyStore::new();
+        let response: MessagingApiResponse = serde_json::from_str(&messaging_body(1)).unwrap();
+
+        store.insert("a", &response, Duration::ZERO).unwrap();
+        store
+            .insert("b", &response, Duration::from_secs(60))
+            .unwrap();
+
+        assert!(store.get("a").unwrap().is_none());
+        assert!(store.get("b").unwrap().is_some());
+    }
+}
@@ -1,15 +1,75 @@
+pub mod analytics;
+pub mod batch;
+pub mod budget;
+pub mod bulk;
+#[cfg(feature = "cassette")]
+pub mod cassette;
 pub mod client;
+pub mod compliance;
 pub mod config;
+pub mod contacts;
+pub mod conversation;
+pub mod dispatch;
 pub mod errors;
+pub mod export;
+pub mod failover;
+pub mod hooks;
+pub mod idempotency;
 pub mod models;
-
-pub use client::UjumbeSmsClient;
+pub mod monitor;
+pub mod otp;
+pub mod phone;
+pub mod rate_limit;
+pub mod request_builder;
+pub mod sender;
+pub mod sending_window;
+pub mod suppression;
+#[cfg(feature = "sqlite")]
+pub mod sync;
+pub mod telemetry;
+#[cfg(test)]
+mod test_support;
+pub mod transport;
+
+pub use analytics::{DeliveryAnalytics, DeliveryReport, DeliveryStats};
+pub use batch::{BagOutcome, BatchSendReport, BatchSummary, RecipientOutcome, RecipientResult};
+pub use budget::{BudgetGuard, LowBalanceAlert};
+pub use bulk::{BulkFailure, BulkProgress, BulkReport, BulkSendHandle, BulkSender};
+#[cfg(feature = "cassette")]
+pub use cassette::CassetteMode;
+pub use client::{ApiEndpoint, UjumbeSmsClient, UjumbeSmsClientBuilder};
+pub use compliance::{ComplianceHook, ComplianceReport, ComplianceRules, Violation};
 pub use config::UjumbeSmsConfig;
+#[cfg(feature = "sqlite")]
+pub use contacts::SqliteContactStore;
+pub use contacts::{Audience, Contact, ContactStore, ImportSummary, InMemoryContactStore};
+pub use conversation::ConversationIndex;
+pub use dispatch::{DispatchHandle, LaneConfig, Priority, PriorityDispatcher};
 pub use errors::UjumbeSmsError;
+pub use export::{Column, ExportFormat, ExportSummary, HistoryExporter};
+pub use failover::{CircuitState, FailoverDelivery, FailoverProvider, SendFuture, SmsProvider};
+pub use hooks::{HookDecision, HookResponse, RequestHook};
+pub use idempotency::{IdempotencyStore, IdempotentClient, InMemoryIdempotencyStore};
 pub use models::{
     BalanceApiResponse, BalanceMetaInfo, DateTime, MessageBag, MessageHistoryApiResponse,
     MessageHistoryMetaInfo, MessageRequest, MessagingApiResponse, MessagingMetaInfo, StatusInfo,
 };
+pub use monitor::{BalanceEvent, BalanceMonitor, BalanceMonitorHandle, BurnRate};
+pub use otp::{InMemoryOtpStore, OtpManager, OtpRecord, OtpSent, OtpStore, OtpVerification};
+pub use rate_limit::RateLimiter;
+pub use request_builder::{MessageBagBuilder, MessageRequestBuilder, RequestIssue};
+pub use sender::{SenderId, SenderRegistry, SenderSubstitution};
+pub use sending_window::{MessageCategory, OutOfWindowAction, SendingWindow, WindowedClient};
+#[cfg(feature = "sqlite")]
+pub use suppression::SqliteSuppressionList;
+pub use suppression::{
+    FileSuppressionList, InMemorySuppressionList, SuppressionList, SuppressionReport,
+};
+#[cfg(feature = "sqlite")]
+pub use sync::{HistoryQuery, HistoryStore, HistorySync, SyncCursor, SyncReport, SyncResume};
+#[cfg(feature = "metrics")]
+pub use telemetry::describe_metrics;
+pub use transport::{BoxError, HttpRequest, HttpResponse, HttpService};
 
 /// UjumbeSMS Rust Client lib tests
 #[cfg(test)]
@@ -458,4 +518,70 @@ mod tests {
             );
         });
     }
+
+    #[test]
+    fn test_get_messages_history_page() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let _mock = server
+                .mock("POST", "/api/messages")
+                .match_query(Matcher::UrlEncoded("page".into(), "2".into()))
+                .with_status(200)
+                .with_header("content-type", "application/json")
+                .with_body(
+                    r#"{
+                        "status": {
+                            "code": "1008",
+                            "type": "success",
+                            "description": "Query Success"
+                        },
+                        "meta": null,
+                        "items": {
+                            "total": 51,
+                            "per_page": 50,
+                            "current_page": 2,
+                            "last_page": 2,
+                            "next_page_url": null,
+                            "prev_page_url": "https://ujumbesms.co.ke/api/messages?page=1",
+                            "from": 51,
+                            "to": 51,
+                            "data": [
+                                {
+                                    "id": 51,
+                                    "request_id": 10051,
+                                    "number": "+254711111111",
+                                    "message": "Page two message",
+                                    "user_id": 3062,
+                                    "sender_id": "UJUMBESMS",
+                                    "transaction_id": "t51",
+                                    "message_count": 1,
+                                    "status": "DeliveredToTerminal",
+                                    "flag": "API|",
+                                    "created_at": "2025-07-20 18:18:11",
+                                    "updated_at": "2025-07-20 18:18:19",
+                                    "scheduled_date": "2025-07-20 18:18:11"
+                                }
+                            ]
+                        }
+                    }"#,
+                )
+                .create();
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@example.com".to_string())
+                    .with_base_url(url);
+            let client = UjumbeSmsClient::new(config).unwrap();
+
+            let result = client.get_messages_history_page(2).await;
+
+            _mock.assert();
+
+            let response = result.unwrap();
+            assert_eq!(response.items.current_page, 2);
+            assert_eq!(response.items.data[0].id, 51);
+        });
+    }
 }
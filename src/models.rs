@@ -1,5 +1,41 @@
+use crate::errors::UjumbeSmsError;
+use crate::phone::{normalize_number, split_numbers};
+use crate::request_builder::MessageRequestBuilder;
+use crate::sender::{SenderRegistry, SenderSubstitution};
 use serde::{Deserialize, Serialize};
 
+/// Characters of the GSM 03.38 basic character set
+const GSM7_BASIC: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?\
+                          ¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+/// Characters of the GSM 03.38 extension table, each encoded with an escape (2 septets)
+const GSM7_EXTENSION: &str = "^{}\\[~]|€\u{000C}";
+
+/// Number of SMS segments needed to deliver `message`.
+/// GSM-7 text fits 160 characters in one segment (153 per part when concatenated),
+/// anything else is sent as UCS-2 with 70 characters (67 per part).
+pub fn sms_segments(message: &str) -> usize {
+    let gsm_septets = message.chars().try_fold(0usize, |septets, c| {
+        if GSM7_BASIC.contains(c) {
+            Some(septets + 1)
+        } else if GSM7_EXTENSION.contains(c) {
+            Some(septets + 2)
+        } else {
+            None
+        }
+    });
+
+    let (length, single, multi) = match gsm_septets {
+        Some(septets) => (septets, 160, 153),
+        None => (message.encode_utf16().count(), 70, 67),
+    };
+
+    match length {
+        0 => 0,
+        l if l <= single => 1,
+        l => l.div_ceil(multi),
+    }
+}
+
 /// `MessageBag` represents the request structure for sending messages
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MessageBag {
@@ -8,6 +44,18 @@ pub struct MessageBag {
     pub sender: String,
 }
 
+impl MessageBag {
+    /// Number of recipients in the comma separated `numbers` field
+    pub fn recipient_count(&self) -> usize {
+        split_numbers(&self.numbers).count()
+    }
+
+    /// Number of SMS segments each recipient receives
+    pub fn segment_count(&self) -> usize {
+        sms_segments(&self.message)
+    }
+}
+
 /// `MessageBagContainer` represents a container for the message bag
 /// This is used to wrap the message bag in a list for the API request.
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -17,7 +65,7 @@ pub struct MessageBagContainer {
 
 /// `MessageRequest` represents the request structure for sending messages
 /// This structure contains a list of message bags to be sent.
-#[derive(Debug, Default, Serialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct MessageRequest {
     pub data: Vec<MessageBagContainer>,
 }
@@ -27,6 +75,11 @@ impl MessageRequest {
         MessageRequest { data: Vec::new() }
     }
 
+    /// Starts a validated request: `MessageRequest::builder().bag(|b| b.to(..).text(..).from(..)).build()`
+    pub fn builder() -> MessageRequestBuilder {
+        MessageRequestBuilder::new()
+    }
+
     pub fn add_message_bag(&mut self, numbers: String, message: String, sender: String) {
         let bag = MessageBag {
             numbers,
@@ -36,6 +89,61 @@ impl MessageRequest {
         let container = MessageBagContainer { message_bag: bag };
         self.data.push(container);
     }
+
+    /// Total number of recipients across all message bags
+    pub fn recipient_count(&self) -> usize {
+        self.data
+            .iter()
+            .map(|c| c.message_bag.recipient_count())
+            .sum()
+    }
+
+    /// Fails with `InvalidSenderId` if any bag uses a sender the registry has not approved
+    pub fn check_senders(&self, registry: &SenderRegistry) -> Result<(), UjumbeSmsError> {
+        match self
+            .data
+            .iter()
+            .find(|c| !registry.is_approved(&c.message_bag.sender))
+        {
+            Some(c) => Err(UjumbeSmsError::InvalidSenderId(format!(
+                "\"{}\" is not an approved sender ID for this account",
+                c.message_bag.sender
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces unapproved senders with the registry's default sender and reports each replacement.
+    /// Nothing is changed if a bag cannot be resolved.
+    pub fn resolve_senders(
+        &mut self,
+        registry: &SenderRegistry,
+    ) -> Result<Vec<SenderSubstitution>, UjumbeSmsError> {
+        let mut substitutions = Vec::new();
+        for (index, c) in self.data.iter().enumerate() {
+            let used = registry.resolve(&c.message_bag.sender)?;
+            if used.as_str() != c.message_bag.sender {
+                substitutions.push(SenderSubstitution {
+                    index,
+                    requested: c.message_bag.sender.clone(),
+                    used,
+                });
+            }
+        }
+
+        for substitution in &substitutions {
+            self.data[substitution.index].message_bag.sender = substitution.used.to_string();
+        }
+        Ok(substitutions)
+    }
+
+    /// Estimated SMS segments the request will be billed for (recipients x segments per bag)
+    pub fn estimated_segments(&self) -> usize {
+        self.data
+            .iter()
+            .map(|c| c.message_bag.recipient_count() * c.message_bag.segment_count())
+            .sum()
+    }
 }
 
 /// `DateTime` structure to represent the date and time information
@@ -151,6 +259,18 @@ pub struct MessageSent {
     pub scheduled_date: String,
 }
 
+impl MessageSent {
+    /// Whether the message reached the handset
+    pub fn is_delivered(&self) -> bool {
+        self.status == "DeliveredToTerminal"
+    }
+
+    /// Whether the message was rejected for a blacklisted or invalid number
+    pub fn is_failed(&self) -> bool {
+        self.status.contains("Blacklisted") || self.status.contains("Invalid")
+    }
+}
+
 // Example usage:
 impl MessageHistoryApiResponse {
     /// Parse JSON string into MessageHistoryApiResponse
@@ -168,7 +288,7 @@ impl MessageHistoryApiResponse {
         self.items
             .data
             .iter()
-            .filter(|msg| msg.status == "DeliveredToTerminal")
+            .filter(|msg| msg.is_delivered())
             .collect()
     }
 
@@ -177,16 +297,54 @@ impl MessageHistoryApiResponse {
         self.items
             .data
             .iter()
-            .filter(|msg| msg.status.contains("Blacklisted") || msg.status.contains("Invalid"))
+            .filter(|msg| msg.is_failed())
             .collect()
     }
 
-    /// Get messages by phone number
+    /// Get messages sent to a phone number, in any format (`0712...`, `+254712...`, `254712...`)
     pub fn get_messages_by_number(&self, number: &str) -> Vec<&MessageSent> {
+        let number = normalize_number(number);
         self.items
             .data
             .iter()
-            .filter(|msg| msg.number.contains(number))
+            .filter(|msg| normalize_number(&msg.number) == number)
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sms_segments() {
+        assert_eq!(sms_segments(""), 0);
+        assert_eq!(sms_segments(&"a".repeat(160)), 1);
+        assert_eq!(sms_segments(&"a".repeat(161)), 2);
+        assert_eq!(sms_segments(&"a".repeat(306)), 2);
+        // Extension characters take two septets
+        assert_eq!(sms_segments(&"€".repeat(80)), 1);
+        assert_eq!(sms_segments(&"€".repeat(81)), 2);
+        // Any non-GSM character switches the whole message to UCS-2
+        assert_eq!(sms_segments(&format!("{}😀", "a".repeat(68))), 1);
+        assert_eq!(sms_segments(&format!("{}😀", "a".repeat(69))), 2);
+    }
+
+    #[test]
+    fn test_request_estimates() {
+        let mut request = MessageRequest::new();
+        request.add_message_bag(
+            "254711111111, 254722222222".to_string(),
+            "a".repeat(200),
+            "UjumbeSMS".to_string(),
+        );
+        request.add_message_bag(
+            "254733333333".to_string(),
+            "Hi".to_string(),
+            "UjumbeSMS".to_string(),
+        );
+
+        assert_eq!(request.recipient_count(), 3);
+        assert_eq!(request.estimated_segments(), 5);
+    }
+}
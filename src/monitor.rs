@@ -0,0 +1,376 @@
+use crate::client::UjumbeSmsClient;
+use crate::models::BalanceMetaInfo;
+use rand::Rng;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+
+/// Default time between balance polls
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Default history used to estimate the burn rate
+pub const DEFAULT_BURN_RATE_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// `BalanceEvent` is emitted by `BalanceMonitor` when the polled balance changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BalanceEvent {
+    Dropped {
+        from: i64,
+        to: i64,
+    },
+    ToppedUp {
+        from: i64,
+        to: i64,
+    },
+    /// The balance fell below `threshold`
+    BelowThreshold {
+        threshold: i64,
+        credits: i64,
+    },
+    /// The balance recovered to or above `threshold`
+    AboveThreshold {
+        threshold: i64,
+        credits: i64,
+    },
+    /// A `balance()` call failed; the monitor keeps polling
+    PollFailed(String),
+}
+
+/// `BurnRate` is the average credit consumption over the monitor's window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BurnRate {
+    pub credits_per_hour: f64,
+    /// Days until credits run out at the current rate; `None` when nothing is being spent
+    pub days_remaining: Option<f64>,
+}
+
+/// `BalanceTracker` turns successive balance readings into events and a burn rate estimate.
+/// Top-ups are excluded from consumption so a refill does not look like negative spend.
+#[derive(Debug)]
+pub struct BalanceTracker {
+    thresholds: Vec<i64>,
+    window: Duration,
+    samples: VecDeque<(Instant, i64)>,
+}
+
+impl BalanceTracker {
+    pub fn new(thresholds: Vec<i64>, window: Duration) -> Self {
+        BalanceTracker {
+            thresholds,
+            window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn last(&self) -> Option<i64> {
+        self.samples.back().map(|(_, credits)| *credits)
+    }
+
+    /// Records a reading taken at `at` and returns the events it triggers
+    pub fn observe(&mut self, credits: i64, at: Instant) -> Vec<BalanceEvent> {
+        let mut events = Vec::new();
+
+        if let Some(previous) = self.last() {
+            if credits < previous {
+                events.push(BalanceEvent::Dropped {
+                    from: previous,
+                    to: credits,
+                });
+            } else if credits > previous {
+                events.push(BalanceEvent::ToppedUp {
+                    from: previous,
+                    to: credits,
+                });
+            }
+
+            for &threshold in &self.thresholds {
+                if previous >= threshold && credits < threshold {
+                    events.push(BalanceEvent::BelowThreshold { threshold, credits });
+                } else if previous < threshold && credits >= threshold {
+                    events.push(BalanceEvent::AboveThreshold { threshold, credits });
+                }
+            }
+        } else {
+            // The first reading only reports thresholds that are already breached
+            for &threshold in &self.thresholds {
+                if credits < threshold {
+                    events.push(BalanceEvent::BelowThreshold { threshold, credits });
+                }
+            }
+        }
+
+        self.samples.push_back((at, credits));
+        while let Some((oldest, _)) = self.samples.front() {
+            if at.duration_since(*oldest) > self.window && self.samples.len() > 2 {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        events
+    }
+
+    /// Average consumption across the retained samples
+    pub fn burn_rate(&self) -> Option<BurnRate> {
+        let (first, _) = self.samples.front()?;
+        let (last, credits) = self.samples.back()?;
+        let hours = last.duration_since(*first).as_secs_f64() / 3600.0;
+        if hours <= 0.0 {
+            return None;
+        }
+
+        let consumed: i64 = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|((_, before), (_, after))| (before - after).max(0))
+            .sum();
+        let credits_per_hour = consumed as f64 / hours;
+        let days_remaining =
+            (credits_per_hour > 0.0).then(|| (*credits).max(0) as f64 / credits_per_hour / 24.0);
+
+        Some(BurnRate {
+            credits_per_hour,
+            days_remaining,
+        })
+    }
+}
+
+/// `BalanceMonitor` polls `UjumbeSmsClient::balance` in the background on a jittered interval
+pub struct BalanceMonitor {
+    client: UjumbeSmsClient,
+    interval: Duration,
+    jitter: Duration,
+    thresholds: Vec<i64>,
+    window: Duration,
+}
+
+impl BalanceMonitor {
+    pub fn new(client: UjumbeSmsClient) -> Self {
+        BalanceMonitor {
+            client,
+            interval: DEFAULT_POLL_INTERVAL,
+            jitter: Duration::from_secs(30),
+            thresholds: Vec::new(),
+            window: DEFAULT_BURN_RATE_WINDOW,
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Maximum random delay added to each interval so that many monitors do not poll in lockstep
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_thresholds(mut self, thresholds: Vec<i64>) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    /// Period of history used for the burn rate estimate
+    pub fn with_burn_rate_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Starts polling on the current Tokio runtime; the first poll happens immediately
+    pub fn spawn(self) -> BalanceMonitorHandle {
+        let (balance_tx, balance_rx) = watch::channel(None);
+        let (events_tx, events_rx) = broadcast::channel(64);
+        let tracker = Arc::new(Mutex::new(BalanceTracker::new(
+            self.thresholds.clone(),
+            self.window,
+        )));
+
+        let task = tokio::spawn(poll_balance(
+            self,
+            balance_tx,
+            events_tx.clone(),
+            tracker.clone(),
+        ));
+
+        BalanceMonitorHandle {
+            events: events_rx,
+            balance: balance_rx,
+            events_tx,
+            tracker,
+            task,
+        }
+    }
+
+    fn next_delay(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return self.interval;
+        }
+        let jitter_nanos = rand::rng().random_range(0..=self.jitter.as_nanos() as u64);
+        self.interval + Duration::from_nanos(jitter_nanos)
+    }
+}
+
+async fn poll_balance(
+    monitor: BalanceMonitor,
+    balance_tx: watch::Sender<Option<BalanceMetaInfo>>,
+    events_tx: broadcast::Sender<BalanceEvent>,
+    tracker: Arc<Mutex<BalanceTracker>>,
+) {
+    loop {
+        match monitor.client.balance().await {
+            Ok(response) => {
+                if let Some(meta) = response.meta {
+                    let events = tracker
+                        .lock()
+                        .expect("balance tracker lock poisoned")
+                        .observe(i64::from(meta.credits), Instant::now());
+                    balance_tx.send_replace(Some(meta));
+                    for event in events {
+                        // No subscribers is not an error for a monitor
+                        let _ = events_tx.send(event);
+                    }
+                }
+            }
+            Err(e) => {
+                let _ = events_tx.send(BalanceEvent::PollFailed(e.to_string()));
+            }
+        }
+
+        tokio::time::sleep(monitor.next_delay()).await;
+    }
+}
+
+/// `BalanceMonitorHandle` gives access to a running `BalanceMonitor`; dropping it stops polling
+pub struct BalanceMonitorHandle {
+    /// Events from the first poll onwards
+    pub events: broadcast::Receiver<BalanceEvent>,
+    balance: watch::Receiver<Option<BalanceMetaInfo>>,
+    events_tx: broadcast::Sender<BalanceEvent>,
+    tracker: Arc<Mutex<BalanceTracker>>,
+    task: JoinHandle<()>,
+}
+
+impl BalanceMonitorHandle {
+    /// Watch channel holding the last `BalanceMetaInfo`, `None` until the first successful poll
+    pub fn balance(&self) -> watch::Receiver<Option<BalanceMetaInfo>> {
+        self.balance.clone()
+    }
+
+    /// Subscribes to balance events emitted from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<BalanceEvent> {
+        self.events_tx.subscribe()
+    }
+
+    pub fn burn_rate(&self) -> Option<BurnRate> {
+        self.tracker
+            .lock()
+            .expect("balance tracker lock poisoned")
+            .burn_rate()
+    }
+
+    /// Stops polling
+    pub fn stop(self) {}
+}
+
+impl Drop for BalanceMonitorHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::balance_body;
+    use crate::UjumbeSmsConfig;
+    use mockito::Server;
+
+    #[test]
+    fn test_tracker_events() {
+        let start = Instant::now();
+        let mut tracker = BalanceTracker::new(vec![100, 50], DEFAULT_BURN_RATE_WINDOW);
+
+        assert_eq!(tracker.observe(120, start), vec![]);
+        assert_eq!(
+            tracker.observe(90, start + Duration::from_secs(60)),
+            vec![
+                BalanceEvent::Dropped { from: 120, to: 90 },
+                BalanceEvent::BelowThreshold {
+                    threshold: 100,
+                    credits: 90
+                },
+            ]
+        );
+        assert_eq!(
+            tracker.observe(500, start + Duration::from_secs(120)),
+            vec![
+                BalanceEvent::ToppedUp { from: 90, to: 500 },
+                BalanceEvent::AboveThreshold {
+                    threshold: 100,
+                    credits: 500
+                },
+            ]
+        );
+        assert_eq!(
+            tracker.observe(500, start + Duration::from_secs(180)),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_burn_rate_ignores_top_ups() {
+        let start = Instant::now();
+        let hour = Duration::from_secs(3600);
+        let mut tracker = BalanceTracker::new(vec![], DEFAULT_BURN_RATE_WINDOW);
+
+        tracker.observe(1000, start);
+        assert_eq!(tracker.burn_rate(), None);
+        tracker.observe(900, start + hour);
+        tracker.observe(2000, start + hour * 2);
+        tracker.observe(1900, start + hour * 4);
+
+        let burn_rate = tracker.burn_rate().unwrap();
+        assert_eq!(burn_rate.credits_per_hour, 50.0);
+        assert_eq!(burn_rate.days_remaining, Some(1900.0 / 50.0 / 24.0));
+    }
+
+    #[test]
+    fn test_monitor_publishes_balance() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let _mock = server
+                .mock("POST", "/api/balance")
+                .with_status(200)
+                .with_header("content-type", "application/json")
+                .with_body(balance_body(40))
+                .create();
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let mut handle = BalanceMonitor::new(UjumbeSmsClient::new(config).unwrap())
+                .with_interval(Duration::from_secs(60))
+                .with_thresholds(vec![50])
+                .spawn();
+
+            let mut balance = handle.balance();
+            balance.changed().await.unwrap();
+            assert_eq!(balance.borrow().as_ref().unwrap().credits, 40);
+            assert_eq!(
+                handle.events.recv().await.unwrap(),
+                BalanceEvent::BelowThreshold {
+                    threshold: 50,
+                    credits: 40
+                }
+            );
+            handle.stop();
+        });
+    }
+}
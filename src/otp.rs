@@ -0,0 +1,518 @@
+use crate::client::UjumbeSmsClient;
+use crate::errors::UjumbeSmsError;
+use crate::models::MessagingApiResponse;
+use crate::phone::normalize_number;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Default number of characters in a code
+pub const DEFAULT_CODE_LENGTH: usize = 6;
+/// Default characters a code is drawn from
+pub const DEFAULT_ALPHABET: &str = "0123456789";
+/// Default message; `{code}` and `{minutes}` are replaced when sending
+pub const DEFAULT_TEMPLATE: &str =
+    "Your verification code is {code}. It expires in {minutes} minutes.";
+/// Default lifetime of a code
+pub const DEFAULT_CODE_TTL: Duration = Duration::from_secs(5 * 60);
+/// Default number of wrong guesses allowed per code
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+/// Default minimum time between two codes sent to the same number
+pub const DEFAULT_RESEND_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// `OtpRecord` is the stored state of the code issued to one number. Only a salted hash of the
+/// code is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtpRecord {
+    /// Hex encoded SHA-256 of the salt, number and code
+    pub hash: String,
+    pub salt: String,
+    pub sent_at: SystemTime,
+    pub expires_at: SystemTime,
+    /// Wrong guesses so far
+    pub attempts: u32,
+}
+
+/// `OtpStore` keeps one `OtpRecord` per normalised number
+pub trait OtpStore: Send + Sync {
+    fn get(&self, number: &str) -> Result<Option<OtpRecord>, UjumbeSmsError>;
+
+    /// Stores `record`, replacing any previous record for `number`
+    fn put(&self, number: &str, record: OtpRecord) -> Result<(), UjumbeSmsError>;
+
+    fn remove(&self, number: &str) -> Result<(), UjumbeSmsError>;
+
+    /// Replaces the record for `number` with the result of `update`, removing it on `None`.
+    /// Must be atomic: no other call may read or write `number` in between, so parallel
+    /// guesses cannot all see the same attempt count.
+    fn update(
+        &self,
+        number: &str,
+        update: &mut dyn FnMut(Option<OtpRecord>) -> Option<OtpRecord>,
+    ) -> Result<(), UjumbeSmsError>;
+}
+
+/// `InMemoryOtpStore` keeps records in process memory; expired records of other numbers are
+/// purged on every `put` and `update`
+#[derive(Debug, Default)]
+pub struct InMemoryOtpStore {
+    records: Mutex<HashMap<String, OtpRecord>>,
+}
+
+impl InMemoryOtpStore {
+    pub fn new() -> Self {
+        InMemoryOtpStore::default()
+    }
+}
+
+impl OtpStore for InMemoryOtpStore {
+    fn get(&self, number: &str) -> Result<Option<OtpRecord>, UjumbeSmsError> {
+        let records = self.records.lock().expect("OTP store lock poisoned");
+        Ok(records.get(number).cloned())
+    }
+
+    fn put(&self, number: &str, record: OtpRecord) -> Result<(), UjumbeSmsError> {
+        let mut records = self.records.lock().expect("OTP store lock poisoned");
+        purge_expired(&mut records);
+        records.insert(number.to_string(), record);
+        Ok(())
+    }
+
+    fn remove(&self, number: &str) -> Result<(), UjumbeSmsError> {
+        let mut records = self.records.lock().expect("OTP store lock poisoned");
+        records.remove(number);
+        Ok(())
+    }
+
+    fn update(
+        &self,
+        number: &str,
+        update: &mut dyn FnMut(Option<OtpRecord>) -> Option<OtpRecord>,
+    ) -> Result<(), UjumbeSmsError> {
+        let mut records = self.records.lock().expect("OTP store lock poisoned");
+        // `number` is taken out first so `update` still sees its expired record
+        let current = records.remove(number);
+        purge_expired(&mut records);
+        if let Some(record) = update(current) {
+            records.insert(number.to_string(), record);
+        }
+        Ok(())
+    }
+}
+
+fn purge_expired(records: &mut HashMap<String, OtpRecord>) {
+    let now = SystemTime::now();
+    records.retain(|_, record| record.expires_at > now);
+}
+
+/// `OtpVerification` is the result of checking a code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtpVerification {
+    /// The code matched and has been consumed
+    Verified,
+    /// Wrong code; the code is locked once `remaining_attempts` reaches zero
+    Invalid {
+        remaining_attempts: u32,
+    },
+    Expired,
+    /// Too many wrong guesses; a new code must be sent
+    Locked,
+    /// No code was sent to this number, or it was already used
+    NotFound,
+}
+
+/// `OtpSent` describes a code that was delivered
+#[derive(Debug, Clone)]
+pub struct OtpSent {
+    pub expires_at: SystemTime,
+    pub response: MessagingApiResponse,
+}
+
+/// `OtpManager` generates one-time passwords, sends them through `UjumbeSmsClient` and verifies
+/// them with an attempt limit and a per-number resend cooldown
+pub struct OtpManager {
+    client: UjumbeSmsClient,
+    store: Box<dyn OtpStore>,
+    sender: String,
+    template: String,
+    length: usize,
+    alphabet: Vec<char>,
+    ttl: Duration,
+    max_attempts: u32,
+    resend_cooldown: Duration,
+}
+
+impl OtpManager {
+    /// Creates a manager backed by an `InMemoryOtpStore`
+    pub fn new(client: UjumbeSmsClient, sender: impl Into<String>) -> Self {
+        OtpManager {
+            client,
+            store: Box::new(InMemoryOtpStore::new()),
+            sender: sender.into(),
+            template: DEFAULT_TEMPLATE.to_string(),
+            length: DEFAULT_CODE_LENGTH,
+            alphabet: DEFAULT_ALPHABET.chars().collect(),
+            ttl: DEFAULT_CODE_TTL,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            resend_cooldown: DEFAULT_RESEND_COOLDOWN,
+        }
+    }
+
+    pub fn with_store(mut self, store: impl OtpStore + 'static) -> Self {
+        self.store = Box::new(store);
+        self
+    }
+
+    /// Message text; `{code}` is replaced by the code and `{minutes}` by the lifetime in minutes
+    pub fn with_template(mut self, template: impl Into<String>) -> Self {
+        self.template = template.into();
+        self
+    }
+
+    pub fn with_length(mut self, length: usize) -> Self {
+        self.length = length.max(1);
+        self
+    }
+
+    /// Characters codes are drawn from, e.g. `"ABCDEFGHJKLMNPQRSTUVWXYZ23456789"`.
+    /// An empty alphabet is ignored.
+    pub fn with_alphabet(mut self, alphabet: &str) -> Self {
+        if !alphabet.is_empty() {
+            self.alphabet = alphabet.chars().collect();
+        }
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_resend_cooldown(mut self, cooldown: Duration) -> Self {
+        self.resend_cooldown = cooldown;
+        self
+    }
+
+    /// A random code of the configured length and alphabet
+    pub fn generate_code(&self) -> String {
+        let mut rng = rand::rng();
+        (0..self.length)
+            .map(|_| self.alphabet[rng.random_range(0..self.alphabet.len())])
+            .collect()
+    }
+
+    /// Sends a new code to `number`, replacing any earlier one.
+    /// Fails with `UjumbeSmsError::OtpCooldown` if the previous code was sent too recently.
+    pub async fn send(&self, number: &str) -> Result<OtpSent, UjumbeSmsError> {
+        let number = normalize_number(number);
+        let now = SystemTime::now();
+
+        let code = self.generate_code();
+        let salt: String = rand::rng()
+            .random::<[u8; 16]>()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let expires_at = now + self.ttl;
+        let issued = OtpRecord {
+            hash: hash_code(&salt, &number, &code),
+            salt,
+            sent_at: now,
+            expires_at,
+            attempts: 0,
+        };
+
+        // Check the cooldown and store the new code in one step, so parallel sends cannot both pass
+        let mut previous = None;
+        let mut cooldown = None;
+        self.store.update(&number, &mut |current| {
+            if let Some(current) = &current {
+                let ready_at = current.sent_at + self.resend_cooldown;
+                if let Ok(retry_after) = ready_at.duration_since(now) {
+                    if !retry_after.is_zero() {
+                        cooldown = Some(retry_after);
+                        return Some(current.clone());
+                    }
+                }
+            }
+            previous = current;
+            Some(issued.clone())
+        })?;
+        if let Some(retry_after) = cooldown {
+            return Err(UjumbeSmsError::OtpCooldown(retry_after));
+        }
+
+        let message = self
+            .template
+            .replace("{code}", &code)
+            .replace("{minutes}", &self.ttl.as_secs().div_ceil(60).to_string());
+        match self
+            .client
+            .send_single_message(&number, &message, &self.sender)
+            .await
+        {
+            Ok(response) => Ok(OtpSent {
+                expires_at,
+                response,
+            }),
+            Err(error) => {
+                // An undelivered code must neither verify nor hold the number in cooldown.
+                // Leave the record alone if something else replaced it meanwhile.
+                self.store.update(&number, &mut |current| match current {
+                    Some(current) if current.hash == issued.hash => previous.take(),
+                    current => current,
+                })?;
+                Err(error)
+            }
+        }
+    }
+
+    /// Checks `code` against the code sent to `number`. A matching code is consumed.
+    pub fn verify(&self, number: &str, code: &str) -> Result<OtpVerification, UjumbeSmsError> {
+        let number = normalize_number(number);
+        let now = SystemTime::now();
+        let mut verification = OtpVerification::NotFound;
+
+        // Read, check and count the attempt in one step, so parallel guesses are all counted
+        self.store.update(&number, &mut |record| {
+            let mut record = record?;
+            if record.expires_at <= now {
+                verification = OtpVerification::Expired;
+                return None;
+            }
+            if record.attempts >= self.max_attempts {
+                verification = OtpVerification::Locked;
+                return Some(record);
+            }
+            if constant_time_eq(
+                hash_code(&record.salt, &number, code.trim()).as_bytes(),
+                record.hash.as_bytes(),
+            ) {
+                verification = OtpVerification::Verified;
+                return None;
+            }
+
+            record.attempts += 1;
+            verification = OtpVerification::Invalid {
+                remaining_attempts: self.max_attempts - record.attempts,
+            };
+            Some(record)
+        })?;
+        Ok(verification)
+    }
+}
+
+fn hash_code(salt: &str, number: &str, code: &str) -> String {
+    let mut hasher = Sha256::new();
+    for field in [salt, number, code] {
+        hasher.update(field.as_bytes());
+        hasher.update([0u8]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Compares without stopping at the first difference, so timing does not leak the hash
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{messaging_body, recording_client};
+    use crate::UjumbeSmsConfig;
+    use mockito::Server;
+
+    #[test]
+    fn test_send_and_verify() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let _mock = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_body(messaging_body(1))
+                .create();
+
+            // Capture the outgoing message text to learn the code
+            let (client, messages) = recording_client(url);
+
+            let otp = OtpManager::new(client, "ACME")
+                .with_template("Code: {code} ({minutes} min)")
+                .with_length(8)
+                .with_alphabet("ABC")
+                .with_ttl(Duration::from_secs(90))
+                .with_max_attempts(2)
+                .with_resend_cooldown(Duration::ZERO);
+
+            let code = otp.generate_code();
+            assert_eq!(code.len(), 8);
+            assert!(code.chars().all(|c| "ABC".contains(c)));
+
+            otp.send("0712345678").await.unwrap();
+            let message = messages.lock().unwrap()[0].clone();
+            assert!(message.ends_with(" (2 min)"), "{message}");
+            let code = &message["Code: ".len().."Code: ".len() + 8];
+
+            assert_eq!(
+                otp.verify("254712345678", "XXXXXXXX").unwrap(),
+                OtpVerification::Invalid {
+                    remaining_attempts: 1
+                }
+            );
+            assert_eq!(
+                otp.verify("+254712345678", code).unwrap(),
+                OtpVerification::Verified
+            );
+            assert_eq!(
+                otp.verify("254712345678", code).unwrap(),
+                OtpVerification::NotFound
+            );
+
+            // Two wrong guesses lock the code, even against the right one
+            otp.send("254712345678").await.unwrap();
+            let code = messages.lock().unwrap()[1]["Code: ".len().."Code: ".len() + 8].to_string();
+            otp.verify("254712345678", "XXXXXXXX").unwrap();
+            otp.verify("254712345678", "XXXXXXXX").unwrap();
+            assert_eq!(
+                otp.verify("254712345678", &code).unwrap(),
+                OtpVerification::Locked
+            );
+
+            let otp = otp.with_resend_cooldown(Duration::from_secs(60));
+            assert!(matches!(
+                otp.send("254712345678").await,
+                Err(UjumbeSmsError::OtpCooldown(retry_after)) if retry_after <= Duration::from_secs(60)
+            ));
+        });
+    }
+
+    #[test]
+    fn test_expired_code() {
+        let config = UjumbeSmsConfig::new("key".to_string(), "test@email.com".to_string());
+        let otp = OtpManager::new(UjumbeSmsClient::new(config).unwrap(), "ACME");
+        let now = SystemTime::now();
+        let record = |number: &str, expires_at: SystemTime| OtpRecord {
+            hash: hash_code("salt", number, "123456"),
+            salt: "salt".to_string(),
+            sent_at: expires_at - DEFAULT_CODE_TTL,
+            expires_at,
+            attempts: 0,
+        };
+
+        otp.store
+            .put("254712345678", record("254712345678", now))
+            .unwrap();
+        assert_eq!(
+            otp.verify("0712345678", "123456").unwrap(),
+            OtpVerification::Expired
+        );
+        assert_eq!(otp.store.get("254712345678").unwrap(), None);
+
+        // Storing a record purges the expired ones
+        otp.store
+            .put("254711111111", record("254711111111", now))
+            .unwrap();
+        otp.store
+            .put(
+                "254722222222",
+                record("254722222222", now + Duration::from_secs(60)),
+            )
+            .unwrap();
+        assert_eq!(otp.store.get("254711111111").unwrap(), None);
+    }
+
+    #[test]
+    fn test_parallel_guesses_share_the_attempt_limit() {
+        let config = UjumbeSmsConfig::new("key".to_string(), "test@email.com".to_string());
+        let otp =
+            OtpManager::new(UjumbeSmsClient::new(config).unwrap(), "ACME").with_max_attempts(3);
+        let now = SystemTime::now();
+        otp.store
+            .put(
+                "254712345678",
+                OtpRecord {
+                    hash: hash_code("salt", "254712345678", "123456"),
+                    salt: "salt".to_string(),
+                    sent_at: now,
+                    expires_at: now + DEFAULT_CODE_TTL,
+                    attempts: 0,
+                },
+            )
+            .unwrap();
+
+        let results: Vec<OtpVerification> = std::thread::scope(|scope| {
+            let guesses: Vec<_> = (0..32)
+                .map(|guess| {
+                    let otp = &otp;
+                    scope.spawn(move || otp.verify("254712345678", &format!("{guess:06}")).unwrap())
+                })
+                .collect();
+            guesses
+                .into_iter()
+                .map(|guess| guess.join().unwrap())
+                .collect()
+        });
+
+        let counted = results
+            .iter()
+            .filter(|result| matches!(result, OtpVerification::Invalid { .. }))
+            .count();
+        assert_eq!(counted, 3);
+        assert_eq!(otp.store.get("254712345678").unwrap().unwrap().attempts, 3);
+        assert_eq!(
+            otp.verify("254712345678", "123456").unwrap(),
+            OtpVerification::Locked
+        );
+    }
+
+    #[test]
+    fn test_send_purges_expired_records() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let _mock = server
+                .mock("POST", "/api/messaging")
+                .with_status(200)
+                .with_body(messaging_body(1))
+                .create();
+
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let otp = OtpManager::new(UjumbeSmsClient::new(config).unwrap(), "ACME");
+            let expired = SystemTime::now() - Duration::from_secs(1);
+            otp.store
+                .put(
+                    "254711111111",
+                    OtpRecord {
+                        hash: hash_code("salt", "254711111111", "123456"),
+                        salt: "salt".to_string(),
+                        sent_at: expired - DEFAULT_CODE_TTL,
+                        expires_at: expired,
+                        attempts: 0,
+                    },
+                )
+                .unwrap();
+
+            otp.send("254722222222").await.unwrap();
+            assert_eq!(otp.store.get("254711111111").unwrap(), None);
+            assert!(otp.store.get("254722222222").unwrap().is_some());
+        });
+    }
+}
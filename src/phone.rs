@@ -0,0 +1,86 @@
+/// Kenyan country calling code used when normalising local numbers
+pub const KENYA_COUNTRY_CODE: &str = "254";
+
+/// Normalises a phone number to the international `2547XXXXXXXX` form used by UjumbeSMS.
+/// Whitespace, dashes, brackets and a leading `+` are removed, and local numbers
+/// (`07XXXXXXXX` or `7XXXXXXXX`) are prefixed with the Kenyan country code.
+/// Numbers that do not look Kenyan are returned with only the punctuation stripped.
+pub fn normalize_number(number: &str) -> String {
+    let digits: String = number
+        .trim()
+        .trim_start_matches('+')
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '(' | ')' | '.'))
+        .collect();
+
+    if digits.len() == 10 && digits.starts_with('0') {
+        format!("{KENYA_COUNTRY_CODE}{}", &digits[1..])
+    } else if digits.len() == 9 && (digits.starts_with('7') || digits.starts_with('1')) {
+        format!("{KENYA_COUNTRY_CODE}{digits}")
+    } else {
+        digits
+    }
+}
+
+/// Masks the subscriber part of a number for logs and exports, keeping the country and
+/// operator prefix and the last two digits: `254712345678` becomes `254712****78`
+pub fn mask_number(number: &str) -> String {
+    let normalized = normalize_number(number);
+    let chars: Vec<char> = normalized.chars().collect();
+    if chars.len() <= 4 {
+        return "*".repeat(chars.len());
+    }
+
+    let keep_start = chars.len().saturating_sub(6).min(6);
+    chars
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            if i < keep_start || i >= chars.len() - 2 {
+                *c
+            } else {
+                '*'
+            }
+        })
+        .collect()
+}
+
+/// Splits the comma separated `MessageBag.numbers` string into trimmed, non-empty numbers
+pub fn split_numbers(numbers: &str) -> impl Iterator<Item = &str> {
+    numbers.split(',').map(str::trim).filter(|n| !n.is_empty())
+}
+
+/// Whether a normalised number is digits only, between 9 and 15 of them (the E.164 maximum)
+pub fn is_plausible_number(number: &str) -> bool {
+    (9..=15).contains(&number.len()) && number.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_number() {
+        assert_eq!(normalize_number("+254711111111"), "254711111111");
+        assert_eq!(normalize_number("254711111111"), "254711111111");
+        assert_eq!(normalize_number("0711 111 111"), "254711111111");
+        assert_eq!(normalize_number("711-111-111"), "254711111111");
+        assert_eq!(normalize_number("0110111111"), "254110111111");
+        assert_eq!(normalize_number("+1 (555) 010-9999"), "15550109999");
+    }
+
+    #[test]
+    fn test_mask_number() {
+        assert_eq!(mask_number("254712345678"), "254712****78");
+        assert_eq!(mask_number("+254 712 345 678"), "254712****78");
+        assert_eq!(mask_number("0712345678"), "254712****78");
+        assert_eq!(mask_number("12345678"), "12****78");
+        assert_eq!(mask_number("1234"), "****");
+    }
+
+    #[test]
+    fn test_split_numbers() {
+        let numbers: Vec<&str> = split_numbers(" 254711111111, ,254722222222,").collect();
+        assert_eq!(numbers, vec!["254711111111", "254722222222"]);
+    }
+}
@@ -0,0 +1,59 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// `RateLimiter` lets at most `permits` operations start in any `per` period by spacing them
+/// evenly, e.g. 10 per second starts one every 100ms. It can be shared between tasks.
+#[derive(Debug)]
+pub struct RateLimiter {
+    spacing: Duration,
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// `permits` of zero is treated as one
+    pub fn new(permits: u32, per: Duration) -> Self {
+        RateLimiter {
+            spacing: per / permits.max(1),
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Time between two operations
+    pub fn spacing(&self) -> Duration {
+        self.spacing
+    }
+
+    /// Waits until the next slot is free and claims it
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next = self.next.lock().expect("rate limiter lock poisoned");
+            let now = Instant::now();
+            let slot = next.map_or(now, |next| next.max(now));
+            *next = Some(slot + self.spacing);
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_acquire_spaces_operations() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let limiter = RateLimiter::new(50, Duration::from_secs(1));
+            assert_eq!(limiter.spacing(), Duration::from_millis(20));
+
+            let start = Instant::now();
+            for _ in 0..4 {
+                limiter.acquire().await;
+            }
+            // The first slot is immediate, the next three are 20ms apart
+            assert!(start.elapsed() >= Duration::from_millis(60));
+        });
+    }
+}
@@ -0,0 +1,366 @@
+use crate::contacts::{Audience, ContactStore};
+use crate::errors::UjumbeSmsError;
+use crate::models::{sms_segments, MessageRequest};
+use crate::phone::{normalize_number, split_numbers};
+use crate::sender::SenderId;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Default limit on the SMS segments a single message body may take
+pub const DEFAULT_MAX_SEGMENTS: usize = 6;
+
+/// `RequestIssue` is one problem found while building a `MessageRequest`.
+/// `bag` is the position of the bag in the order it was added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestIssue {
+    EmptyRequest,
+    EmptyNumbers {
+        bag: usize,
+    },
+    EmptyMessage {
+        bag: usize,
+    },
+    EmptySender {
+        bag: usize,
+    },
+    InvalidSender {
+        bag: usize,
+        reason: String,
+    },
+    DuplicateRecipient {
+        bag: usize,
+        number: String,
+    },
+    MessageTooLong {
+        bag: usize,
+        segments: usize,
+        max_segments: usize,
+    },
+    /// The bag targets a group or tag but was built without a `ContactStore`
+    UnresolvedAudience {
+        bag: usize,
+    },
+}
+
+impl fmt::Display for RequestIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestIssue::EmptyRequest => write!(f, "request has no message bags"),
+            RequestIssue::EmptyNumbers { bag } => write!(f, "bag {bag} has no recipients"),
+            RequestIssue::EmptyMessage { bag } => write!(f, "bag {bag} has an empty message"),
+            RequestIssue::EmptySender { bag } => write!(f, "bag {bag} has no sender"),
+            RequestIssue::InvalidSender { bag, reason } => {
+                write!(f, "bag {bag} has an invalid sender: {reason}")
+            }
+            RequestIssue::DuplicateRecipient { bag, number } => {
+                write!(f, "bag {bag} lists {number} more than once")
+            }
+            RequestIssue::MessageTooLong {
+                bag,
+                segments,
+                max_segments,
+            } => write!(
+                f,
+                "bag {bag} needs {segments} SMS segments, the maximum is {max_segments}"
+            ),
+            RequestIssue::UnresolvedAudience { bag } => write!(
+                f,
+                "bag {bag} targets contacts; build it with `build_with_contacts`"
+            ),
+        }
+    }
+}
+
+/// `MessageBagBuilder` collects the fields of one bag inside `MessageRequestBuilder::bag`
+#[derive(Debug, Clone, Default)]
+pub struct MessageBagBuilder {
+    numbers: Vec<String>,
+    audiences: Vec<Audience>,
+    message: String,
+    sender: String,
+}
+
+impl MessageBagBuilder {
+    /// Adds recipients; `numbers` may be comma separated. Local numbers are normalised to `254...`.
+    pub fn to(mut self, numbers: impl AsRef<str>) -> Self {
+        self.numbers
+            .extend(split_numbers(numbers.as_ref()).map(normalize_number));
+        self
+    }
+
+    /// Adds each number in `numbers` as a recipient
+    pub fn to_all<I, S>(mut self, numbers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for number in numbers {
+            self = self.to(number);
+        }
+        self
+    }
+
+    /// Adds the members of a contact group, expanded by `MessageRequestBuilder::build_with_contacts`
+    pub fn to_group(self, group: impl Into<String>) -> Self {
+        self.to_audience(Audience::Group(group.into()))
+    }
+
+    /// Adds every contact with `tag`
+    pub fn to_tagged(self, tag: impl Into<String>) -> Self {
+        self.to_audience(Audience::Tag(tag.into()))
+    }
+
+    pub fn to_audience(mut self, audience: Audience) -> Self {
+        self.audiences.push(audience);
+        self
+    }
+
+    pub fn text(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    pub fn from(mut self, sender: impl Into<String>) -> Self {
+        self.sender = sender.into();
+        self
+    }
+}
+
+/// `MessageRequestBuilder` builds a `MessageRequest`, validating every bag in `build`
+#[derive(Debug, Clone)]
+pub struct MessageRequestBuilder {
+    bags: Vec<MessageBagBuilder>,
+    max_segments: usize,
+    dedupe_across_bags: bool,
+}
+
+impl Default for MessageRequestBuilder {
+    fn default() -> Self {
+        MessageRequestBuilder {
+            bags: Vec::new(),
+            max_segments: DEFAULT_MAX_SEGMENTS,
+            dedupe_across_bags: false,
+        }
+    }
+}
+
+impl MessageRequestBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a bag configured by `configure`, e.g. `|b| b.to("254712345678").text("Hi").from("ACME")`
+    pub fn bag(mut self, configure: impl FnOnce(MessageBagBuilder) -> MessageBagBuilder) -> Self {
+        self.bags.push(configure(MessageBagBuilder::default()));
+        self
+    }
+
+    /// Maximum SMS segments per message body (6 by default)
+    pub fn with_max_segments(mut self, max_segments: usize) -> Self {
+        self.max_segments = max_segments;
+        self
+    }
+
+    /// Drops recipients already listed in an earlier bag, and bags left with no recipients
+    pub fn with_dedupe_across_bags(mut self, dedupe: bool) -> Self {
+        self.dedupe_across_bags = dedupe;
+        self
+    }
+
+    /// Expands the groups and tags of every bag from `contacts`, then builds as `build` does.
+    /// A contact matched by several audiences, or also listed directly, is sent to once.
+    pub fn build_with_contacts(
+        mut self,
+        contacts: &dyn ContactStore,
+    ) -> Result<MessageRequest, UjumbeSmsError> {
+        for builder in &mut self.bags {
+            let mut seen: HashSet<String> = builder.numbers.iter().cloned().collect();
+            for audience in std::mem::take(&mut builder.audiences) {
+                for number in contacts.resolve(&audience)? {
+                    if seen.insert(number.clone()) {
+                        builder.numbers.push(number);
+                    }
+                }
+            }
+        }
+        self.build()
+    }
+
+    /// Validates every bag and returns the request, or `UjumbeSmsError::InvalidRequest`
+    /// listing every issue found
+    pub fn build(self) -> Result<MessageRequest, UjumbeSmsError> {
+        let mut issues = Vec::new();
+        let mut request = MessageRequest::new();
+        let mut seen_in_request = HashSet::new();
+
+        if self.bags.is_empty() {
+            issues.push(RequestIssue::EmptyRequest);
+        }
+
+        for (bag, builder) in self.bags.into_iter().enumerate() {
+            let mut seen_in_bag = HashSet::new();
+            for number in &builder.numbers {
+                if !seen_in_bag.insert(number.as_str()) {
+                    issues.push(RequestIssue::DuplicateRecipient {
+                        bag,
+                        number: number.clone(),
+                    });
+                }
+            }
+
+            let numbers: Vec<&str> = if self.dedupe_across_bags {
+                builder
+                    .numbers
+                    .iter()
+                    .filter(|n| !seen_in_request.contains(n.as_str()))
+                    .map(String::as_str)
+                    .collect()
+            } else {
+                builder.numbers.iter().map(String::as_str).collect()
+            };
+
+            if !builder.audiences.is_empty() {
+                issues.push(RequestIssue::UnresolvedAudience { bag });
+            } else if builder.numbers.is_empty() {
+                issues.push(RequestIssue::EmptyNumbers { bag });
+            }
+            if builder.message.trim().is_empty() {
+                issues.push(RequestIssue::EmptyMessage { bag });
+            }
+            if builder.sender.trim().is_empty() {
+                issues.push(RequestIssue::EmptySender { bag });
+            } else if let Err(UjumbeSmsError::InvalidSenderId(reason)) =
+                SenderId::new(builder.sender.as_str())
+            {
+                issues.push(RequestIssue::InvalidSender { bag, reason });
+            }
+            let segments = sms_segments(&builder.message);
+            if segments > self.max_segments {
+                issues.push(RequestIssue::MessageTooLong {
+                    bag,
+                    segments,
+                    max_segments: self.max_segments,
+                });
+            }
+
+            if !numbers.is_empty() {
+                request.add_message_bag(numbers.join(","), builder.message, builder.sender);
+            }
+            seen_in_request.extend(builder.numbers);
+        }
+
+        if issues.is_empty() {
+            Ok(request)
+        } else {
+            Err(UjumbeSmsError::InvalidRequest(issues))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_builds_valid_request() {
+        let request = MessageRequest::builder()
+            .bag(|b| {
+                b.to("0712 345 678, +254798765432")
+                    .text("Hello")
+                    .from("ACME")
+            })
+            .bag(|b| {
+                b.to_all(["254712345678", "254711111111"])
+                    .text("Hi again")
+                    .from("ACME")
+            })
+            .with_dedupe_across_bags(true)
+            .build()
+            .unwrap();
+
+        assert_eq!(request.data.len(), 2);
+        assert_eq!(
+            request.data[0].message_bag.numbers,
+            "254712345678,254798765432"
+        );
+        // The repeated recipient is only kept in the first bag
+        assert_eq!(request.data[1].message_bag.numbers, "254711111111");
+    }
+
+    #[test]
+    fn test_builder_reports_every_issue() {
+        assert!(matches!(
+            MessageRequest::builder().build(),
+            Err(UjumbeSmsError::InvalidRequest(issues)) if issues == vec![RequestIssue::EmptyRequest]
+        ));
+
+        let result = MessageRequest::builder()
+            .bag(|b| b.text("No recipients").from("ACME"))
+            .bag(|b| b.to("254712345678,0712345678").text(" ").from("Bad-Sender"))
+            .bag(|b| b.to("254712345678").text("x".repeat(200)).from("ACME"))
+            .with_max_segments(1)
+            .build();
+
+        match result {
+            Err(UjumbeSmsError::InvalidRequest(issues)) => {
+                assert_eq!(issues.len(), 5, "{issues:?}");
+                assert_eq!(issues[0], RequestIssue::EmptyNumbers { bag: 0 });
+                assert_eq!(
+                    issues[1],
+                    RequestIssue::DuplicateRecipient {
+                        bag: 1,
+                        number: "254712345678".to_string()
+                    }
+                );
+                assert_eq!(issues[2], RequestIssue::EmptyMessage { bag: 1 });
+                assert!(matches!(
+                    issues[3],
+                    RequestIssue::InvalidSender { bag: 1, .. }
+                ));
+                assert_eq!(
+                    issues[4],
+                    RequestIssue::MessageTooLong {
+                        bag: 2,
+                        segments: 2,
+                        max_segments: 1
+                    }
+                );
+            }
+            other => panic!("Expected InvalidRequest, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_builder_expands_contact_audiences() {
+        use crate::contacts::{Contact, InMemoryContactStore};
+
+        let contacts = InMemoryContactStore::new();
+        contacts
+            .upsert(Contact::new("0711111111").with_tag("vip"))
+            .unwrap();
+        contacts
+            .upsert(Contact::new("0722222222").with_tag("vip"))
+            .unwrap();
+        contacts.add_to_group("staff", "0722222222").unwrap();
+        contacts.add_to_group("staff", "0733333333").unwrap();
+
+        let builder = MessageRequest::builder().bag(|b| {
+            b.to("254711111111")
+                .to_tagged("vip")
+                .to_group("staff")
+                .text("Hello")
+                .from("ACME")
+        });
+        assert!(matches!(
+            builder.clone().build(),
+            Err(UjumbeSmsError::InvalidRequest(issues))
+                if issues == vec![RequestIssue::UnresolvedAudience { bag: 0 }]
+        ));
+
+        let request = builder.build_with_contacts(&contacts).unwrap();
+        assert_eq!(
+            request.data[0].message_bag.numbers,
+            "254711111111,254722222222,254733333333"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use crate::errors::UjumbeSmsError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Maximum length of an alphanumeric sender ID
+pub const MAX_SENDER_ID_LEN: usize = 11;
+
+/// The shared sender ID every UjumbeSMS account may use
+pub const DEFAULT_SENDER_ID: &str = "UjumbeSMS";
+
+/// `SenderId` is a validated alphanumeric sender ID: 1 to 11 ASCII letters, digits or spaces,
+/// containing at least one letter and not starting or ending with a space
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SenderId(String);
+
+impl SenderId {
+    pub fn new(sender: impl Into<String>) -> Result<Self, UjumbeSmsError> {
+        let sender = sender.into();
+        let invalid = |reason: &str| {
+            Err(UjumbeSmsError::InvalidSenderId(format!(
+                "\"{sender}\" {reason}"
+            )))
+        };
+
+        if sender.is_empty() {
+            return invalid("is empty");
+        }
+        if sender.chars().count() > MAX_SENDER_ID_LEN {
+            return invalid("is longer than 11 characters");
+        }
+        if let Some(c) = sender
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || *c == ' '))
+        {
+            return invalid(&format!("contains the disallowed character '{c}'"));
+        }
+        if sender.starts_with(' ') || sender.ends_with(' ') {
+            return invalid("starts or ends with a space");
+        }
+        if !sender.chars().any(|c| c.is_ascii_alphabetic()) {
+            return invalid("contains no letters");
+        }
+
+        Ok(SenderId(sender))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for SenderId {
+    fn default() -> Self {
+        SenderId(DEFAULT_SENDER_ID.to_string())
+    }
+}
+
+impl fmt::Display for SenderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for SenderId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::str::FromStr for SenderId {
+    type Err = UjumbeSmsError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SenderId::new(s)
+    }
+}
+
+impl TryFrom<String> for SenderId {
+    type Error = UjumbeSmsError;
+    fn try_from(sender: String) -> Result<Self, Self::Error> {
+        SenderId::new(sender)
+    }
+}
+
+impl From<SenderId> for String {
+    fn from(sender: SenderId) -> Self {
+        sender.0
+    }
+}
+
+/// `SenderSubstitution` records a bag whose sender was replaced by the registry default
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SenderSubstitution {
+    /// Position of the bag in `MessageRequest.data`
+    pub index: usize,
+    pub requested: String,
+    pub used: SenderId,
+}
+
+/// `SenderRegistry` holds the sender IDs approved for an account.
+/// Senders are matched exactly, so "UjumbeSms" does not match an approved "UjumbeSMS".
+#[derive(Debug, Clone, Default)]
+pub struct SenderRegistry {
+    approved: BTreeSet<SenderId>,
+    default_sender: Option<SenderId>,
+}
+
+impl SenderRegistry {
+    pub fn new(approved: impl IntoIterator<Item = SenderId>) -> Self {
+        SenderRegistry {
+            approved: approved.into_iter().collect(),
+            default_sender: None,
+        }
+    }
+
+    /// Sender used in place of unapproved ones; it is approved implicitly
+    pub fn with_default_sender(mut self, sender: SenderId) -> Self {
+        self.approved.insert(sender.clone());
+        self.default_sender = Some(sender);
+        self
+    }
+
+    pub fn approve(&mut self, sender: SenderId) {
+        self.approved.insert(sender);
+    }
+
+    pub fn is_approved(&self, sender: &str) -> bool {
+        self.approved
+            .iter()
+            .any(|approved| approved.as_str() == sender)
+    }
+
+    pub fn default_sender(&self) -> Option<&SenderId> {
+        self.default_sender.as_ref()
+    }
+
+    pub fn approved(&self) -> impl Iterator<Item = &SenderId> {
+        self.approved.iter()
+    }
+
+    /// Returns the sender to use for `sender`: itself when approved, otherwise the default sender.
+    /// Fails with `InvalidSenderId` when the sender is unapproved and no default is configured.
+    pub fn resolve(&self, sender: &str) -> Result<SenderId, UjumbeSmsError> {
+        if self.is_approved(sender) {
+            return SenderId::new(sender);
+        }
+        self.default_sender.clone().ok_or_else(|| {
+            UjumbeSmsError::InvalidSenderId(format!(
+                "\"{sender}\" is not an approved sender ID for this account"
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MessageRequest;
+
+    fn sender(s: &str) -> SenderId {
+        SenderId::new(s).unwrap()
+    }
+
+    #[test]
+    fn test_sender_id_rules() {
+        assert_eq!(sender("UjumbeSMS").as_str(), "UjumbeSMS");
+        assert!(SenderId::new("Acme Shop").is_ok());
+        assert!(SenderId::new("ACME2024").is_ok());
+        assert!(SenderId::new("").is_err());
+        assert!(SenderId::new("TwelveCharsX").is_err());
+        assert!(SenderId::new("Acme-Shop").is_err());
+        assert!(SenderId::new(" Acme").is_err());
+        assert!(SenderId::new("12345").is_err());
+        assert!(matches!(
+            "Acme_Shop".parse::<SenderId>(),
+            Err(UjumbeSmsError::InvalidSenderId(msg)) if msg.contains("'_'")
+        ));
+        assert!(serde_json::from_str::<SenderId>("\"Acme!\"").is_err());
+        assert_eq!(SenderId::default().as_str(), DEFAULT_SENDER_ID);
+    }
+
+    #[test]
+    fn test_registry_resolves_senders() {
+        let registry =
+            SenderRegistry::new([sender("ACME")]).with_default_sender(sender("UjumbeSMS"));
+        assert_eq!(registry.resolve("ACME").unwrap(), sender("ACME"));
+        assert_eq!(registry.resolve("UjumbeSms").unwrap(), sender("UjumbeSMS"));
+
+        let strict = SenderRegistry::new([sender("ACME")]);
+        assert!(matches!(
+            strict.resolve("Acme"),
+            Err(UjumbeSmsError::InvalidSenderId(_))
+        ));
+
+        let mut request = MessageRequest::new();
+        request.add_message_bag("254712345678".into(), "Hi".into(), "ACME".into());
+        request.add_message_bag("254712345678".into(), "Hi".into(), "UjumbeSms".into());
+
+        assert!(request.check_senders(&strict).is_err());
+        let substitutions = request.resolve_senders(&registry).unwrap();
+        assert_eq!(
+            substitutions,
+            vec![SenderSubstitution {
+                index: 1,
+                requested: "UjumbeSms".to_string(),
+                used: sender("UjumbeSMS"),
+            }]
+        );
+        assert_eq!(request.data[1].message_bag.sender, "UjumbeSMS");
+        assert!(request.check_senders(&registry).is_ok());
+    }
+}
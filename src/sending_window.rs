@@ -0,0 +1,314 @@
+use crate::client::UjumbeSmsClient;
+use crate::errors::UjumbeSmsError;
+use crate::models::{MessageRequest, MessagingApiResponse};
+use chrono::{DateTime, Datelike, Days, FixedOffset, NaiveTime, Utc, Weekday};
+
+/// Returns the `Africa/Nairobi` offset (EAT, UTC+03:00).
+/// Kenya does not observe daylight saving time so a fixed offset is exact.
+pub fn nairobi() -> FixedOffset {
+    FixedOffset::east_opt(3 * 3600).expect("UTC+03:00 is a valid offset")
+}
+
+/// Current wall-clock time in `Africa/Nairobi`
+pub fn nairobi_now() -> DateTime<FixedOffset> {
+    Utc::now().with_timezone(&nairobi())
+}
+
+/// `MessageCategory` classifies outbound traffic for policy decisions.
+/// Transactional messages (OTPs, alerts, receipts) may be exempt from quiet hours,
+/// promotional messages never are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MessageCategory {
+    Transactional,
+    #[default]
+    Promotional,
+}
+
+/// `OutOfWindowAction` decides what happens to a message submitted outside the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutOfWindowAction {
+    /// Wait until the next open slot and send then
+    Defer,
+    /// Fail immediately with `UjumbeSmsError::OutsideSendingWindow`
+    Reject,
+}
+
+/// `DailyWindow` is the half-open interval `[start, end)` during which sending is allowed on a day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DailyWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+/// `WindowDecision` is the outcome of checking a message against a `SendingWindow`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowDecision {
+    Send,
+    Defer(DateTime<FixedOffset>),
+    Reject(Option<DateTime<FixedOffset>>),
+}
+
+/// `SendingWindow` holds the allowed sending hours per weekday, evaluated in `Africa/Nairobi` time.
+/// The default window is 08:00 - 20:00 every day, defers out-of-window messages and
+/// lets transactional messages through at any hour.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendingWindow {
+    days: [Option<DailyWindow>; 7],
+    action: OutOfWindowAction,
+    exempt_transactional: bool,
+}
+
+impl Default for SendingWindow {
+    fn default() -> Self {
+        let daytime = DailyWindow {
+            start: NaiveTime::from_hms_opt(8, 0, 0).expect("valid time"),
+            end: NaiveTime::from_hms_opt(20, 0, 0).expect("valid time"),
+        };
+        SendingWindow {
+            days: [Some(daytime); 7],
+            action: OutOfWindowAction::Defer,
+            exempt_transactional: true,
+        }
+    }
+}
+
+impl SendingWindow {
+    /// Creates a window that is closed on every day; open days are added with `with_day`
+    pub fn closed() -> Self {
+        SendingWindow {
+            days: [None; 7],
+            ..SendingWindow::default()
+        }
+    }
+
+    /// Opens `weekday` between `start` (inclusive) and `end` (exclusive).
+    /// Windows spanning midnight are not supported; `start` must be before `end`.
+    pub fn with_day(
+        mut self,
+        weekday: Weekday,
+        start: NaiveTime,
+        end: NaiveTime,
+    ) -> Result<Self, UjumbeSmsError> {
+        if start >= end {
+            return Err(UjumbeSmsError::InvalidConfig(format!(
+                "Sending window for {weekday} must start before it ends"
+            )));
+        }
+        self.days[weekday.num_days_from_monday() as usize] = Some(DailyWindow { start, end });
+        Ok(self)
+    }
+
+    /// Closes `weekday` entirely
+    pub fn closed_on(mut self, weekday: Weekday) -> Self {
+        self.days[weekday.num_days_from_monday() as usize] = None;
+        self
+    }
+
+    pub fn with_action(mut self, action: OutOfWindowAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Whether transactional messages bypass the window (default: `true`)
+    pub fn with_transactional_exemption(mut self, exempt: bool) -> Self {
+        self.exempt_transactional = exempt;
+        self
+    }
+
+    pub fn day(&self, weekday: Weekday) -> Option<DailyWindow> {
+        self.days[weekday.num_days_from_monday() as usize]
+    }
+
+    pub fn action(&self) -> OutOfWindowAction {
+        self.action
+    }
+
+    /// Whether sending is allowed at `at`
+    pub fn is_open_at(&self, at: DateTime<FixedOffset>) -> bool {
+        let at = at.with_timezone(&nairobi());
+        match self.day(at.weekday()) {
+            Some(window) => window.start <= at.time() && at.time() < window.end,
+            None => false,
+        }
+    }
+
+    /// Earliest instant at or after `at` when sending is allowed.
+    /// Returns `None` when the window is closed on every day.
+    pub fn next_open(&self, at: DateTime<FixedOffset>) -> Option<DateTime<FixedOffset>> {
+        let at = at.with_timezone(&nairobi());
+        if self.is_open_at(at) {
+            return Some(at);
+        }
+
+        (0..=7).find_map(|offset| {
+            let date = at.date_naive().checked_add_days(Days::new(offset))?;
+            let window = self.day(date.weekday())?;
+            if offset == 0 && at.time() >= window.start {
+                return None;
+            }
+            date.and_time(window.start)
+                .and_local_timezone(nairobi())
+                .single()
+        })
+    }
+
+    /// Decides whether a message of `category` submitted at `at` is sent, deferred or rejected
+    pub fn check(&self, category: MessageCategory, at: DateTime<FixedOffset>) -> WindowDecision {
+        if (category == MessageCategory::Transactional && self.exempt_transactional)
+            || self.is_open_at(at)
+        {
+            return WindowDecision::Send;
+        }
+
+        let next_open = self.next_open(at);
+        match (self.action, next_open) {
+            (OutOfWindowAction::Defer, Some(next_open)) => WindowDecision::Defer(next_open),
+            _ => WindowDecision::Reject(next_open),
+        }
+    }
+}
+
+/// `WindowedClient` wraps `UjumbeSmsClient` and enforces a `SendingWindow` before every send
+pub struct WindowedClient {
+    client: UjumbeSmsClient,
+    window: SendingWindow,
+}
+
+impl WindowedClient {
+    pub fn new(client: UjumbeSmsClient, window: SendingWindow) -> Self {
+        WindowedClient { client, window }
+    }
+
+    pub fn client(&self) -> &UjumbeSmsClient {
+        &self.client
+    }
+
+    pub fn window(&self) -> &SendingWindow {
+        &self.window
+    }
+
+    /// Sends `request` if the window is open, otherwise defers or rejects it per the window's action
+    pub async fn send_messages(
+        &self,
+        request: MessageRequest,
+        category: MessageCategory,
+    ) -> Result<MessagingApiResponse, UjumbeSmsError> {
+        let now = nairobi_now();
+        match self.window.check(category, now) {
+            WindowDecision::Send => {}
+            WindowDecision::Defer(next_open) => {
+                let wait = (next_open - now).to_std().unwrap_or_default();
+                tokio::time::sleep(wait).await;
+            }
+            WindowDecision::Reject(next_open) => {
+                return Err(UjumbeSmsError::OutsideSendingWindow(next_open));
+            }
+        }
+
+        self.client.send_messages(request).await
+    }
+
+    /// Convenience method to send a single message while honouring the window
+    pub async fn send_single_message(
+        &self,
+        numbers: &str,
+        message: &str,
+        sender: &str,
+        category: MessageCategory,
+    ) -> Result<MessagingApiResponse, UjumbeSmsError> {
+        let mut request = MessageRequest::new();
+        request.add_message_bag(numbers.to_string(), message.to_string(), sender.to_string());
+        self.send_messages(request, category).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<FixedOffset> {
+        nairobi().with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_default_window_open_and_closed() {
+        let window = SendingWindow::default();
+
+        // 2025-07-21 is a Monday
+        assert!(window.is_open_at(at(2025, 7, 21, 8, 0)));
+        assert!(window.is_open_at(at(2025, 7, 21, 19, 59)));
+        assert!(!window.is_open_at(at(2025, 7, 21, 20, 0)));
+        assert!(!window.is_open_at(at(2025, 7, 21, 3, 0)));
+    }
+
+    #[test]
+    fn test_next_open_rolls_over_closed_days() {
+        let window = SendingWindow::default()
+            .closed_on(Weekday::Sat)
+            .closed_on(Weekday::Sun);
+
+        // Friday night defers to Monday morning
+        assert_eq!(
+            window.next_open(at(2025, 7, 25, 21, 30)),
+            Some(at(2025, 7, 28, 8, 0))
+        );
+        // Early morning defers to the same day
+        assert_eq!(
+            window.next_open(at(2025, 7, 21, 6, 15)),
+            Some(at(2025, 7, 21, 8, 0))
+        );
+        assert_eq!(
+            SendingWindow::closed().next_open(at(2025, 7, 21, 6, 15)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_next_open_converts_from_utc() {
+        let window = SendingWindow::default();
+        // 04:30 UTC is 07:30 in Nairobi
+        let utc = Utc.with_ymd_and_hms(2025, 7, 21, 4, 30, 0).unwrap();
+        assert_eq!(
+            window.next_open(utc.fixed_offset()),
+            Some(at(2025, 7, 21, 8, 0))
+        );
+    }
+
+    #[test]
+    fn test_check_decisions() {
+        let night = at(2025, 7, 21, 23, 0);
+        let deferring = SendingWindow::default();
+        let rejecting = SendingWindow::default().with_action(OutOfWindowAction::Reject);
+
+        assert_eq!(
+            deferring.check(MessageCategory::Transactional, night),
+            WindowDecision::Send
+        );
+        assert_eq!(
+            deferring.check(MessageCategory::Promotional, night),
+            WindowDecision::Defer(at(2025, 7, 22, 8, 0))
+        );
+        assert_eq!(
+            rejecting.check(MessageCategory::Promotional, night),
+            WindowDecision::Reject(Some(at(2025, 7, 22, 8, 0)))
+        );
+        assert_eq!(
+            deferring
+                .clone()
+                .with_transactional_exemption(false)
+                .check(MessageCategory::Transactional, night),
+            WindowDecision::Defer(at(2025, 7, 22, 8, 0))
+        );
+    }
+
+    #[test]
+    fn test_with_day_rejects_inverted_window() {
+        let result = SendingWindow::closed().with_day(
+            Weekday::Mon,
+            NaiveTime::from_hms_opt(18, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+        );
+        assert!(matches!(result, Err(UjumbeSmsError::InvalidConfig(_))));
+    }
+}
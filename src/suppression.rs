@@ -0,0 +1,491 @@
+use crate::errors::UjumbeSmsError;
+use crate::models::{MessageBagContainer, MessageHistoryApiResponse, MessageRequest};
+use crate::phone::{normalize_number, split_numbers};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Replies that opt a recipient out of further messages (matched case-insensitively)
+pub const STOP_KEYWORDS: [&str; 6] = ["STOP", "STOPALL", "UNSUBSCRIBE", "CANCEL", "END", "QUIT"];
+
+/// `SuppressionReason` records why a number was added to a suppression list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionReason {
+    /// The network reported the number as blacklisted in message history
+    Blacklisted,
+    /// The recipient replied with a STOP keyword
+    OptOut,
+    /// Added by hand
+    Manual,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::Blacklisted => "blacklisted",
+            SuppressionReason::OptOut => "opt_out",
+            SuppressionReason::Manual => "manual",
+        }
+    }
+}
+
+impl std::str::FromStr for SuppressionReason {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blacklisted" => Ok(SuppressionReason::Blacklisted),
+            "opt_out" => Ok(SuppressionReason::OptOut),
+            "manual" => Ok(SuppressionReason::Manual),
+            _ => Err(()),
+        }
+    }
+}
+
+/// `SuppressedNumber` is a single entry of a suppression list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuppressedNumber {
+    pub number: String,
+    pub reason: SuppressionReason,
+}
+
+/// `StrippedRecipients` lists the numbers removed from one message bag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StrippedRecipients {
+    /// Index of the bag in the original `MessageRequest.data`
+    pub bag_index: usize,
+    pub numbers: Vec<String>,
+}
+
+/// `SuppressionReport` describes what `SuppressionList::filter_request` removed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SuppressionReport {
+    pub stripped: Vec<StrippedRecipients>,
+    /// Indexes of bags dropped entirely because every recipient was suppressed
+    pub dropped_bags: Vec<usize>,
+}
+
+impl SuppressionReport {
+    /// Total number of recipients stripped across all bags
+    pub fn total_stripped(&self) -> usize {
+        self.stripped.iter().map(|s| s.numbers.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stripped.is_empty()
+    }
+}
+
+/// Whether an inbound reply asks to opt out of further messages
+pub fn is_stop_reply(text: &str) -> bool {
+    let keyword: String = text
+        .trim()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    STOP_KEYWORDS.contains(&keyword.as_str())
+}
+
+/// `SuppressionList` is a store of numbers that must never be messaged.
+/// Implementations normalise numbers with `phone::normalize_number` so that
+/// `+254711111111`, `254711111111` and `0711111111` are the same entry.
+pub trait SuppressionList: Send + Sync {
+    fn contains(&self, number: &str) -> Result<bool, UjumbeSmsError>;
+
+    /// Adds `number`, returning `true` if it was not suppressed already
+    fn add(&self, number: &str, reason: SuppressionReason) -> Result<bool, UjumbeSmsError>;
+
+    /// Removes `number`, returning `true` if it was suppressed
+    fn remove(&self, number: &str) -> Result<bool, UjumbeSmsError>;
+
+    fn entries(&self) -> Result<Vec<SuppressedNumber>, UjumbeSmsError>;
+
+    /// Adds every number reported as Blacklisted in `history`, returning the newly added numbers
+    fn absorb_history(
+        &self,
+        history: &MessageHistoryApiResponse,
+    ) -> Result<Vec<String>, UjumbeSmsError> {
+        let mut added = Vec::new();
+        for message in history
+            .items
+            .data
+            .iter()
+            .filter(|msg| msg.status.contains("Blacklisted"))
+        {
+            if self.add(&message.number, SuppressionReason::Blacklisted)? {
+                added.push(normalize_number(&message.number));
+            }
+        }
+        Ok(added)
+    }
+
+    /// Suppresses `number` if `reply` is a STOP keyword, returning whether it was newly added
+    fn record_reply(&self, number: &str, reply: &str) -> Result<bool, UjumbeSmsError> {
+        if is_stop_reply(reply) {
+            self.add(number, SuppressionReason::OptOut)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Strips suppressed numbers from every bag of `request`.
+    /// Bags left without recipients are removed from the returned request.
+    fn filter_request(
+        &self,
+        request: MessageRequest,
+    ) -> Result<(MessageRequest, SuppressionReport), UjumbeSmsError> {
+        let mut report = SuppressionReport::default();
+        let mut data = Vec::with_capacity(request.data.len());
+
+        for (bag_index, container) in request.data.into_iter().enumerate() {
+            let mut kept = Vec::new();
+            let mut stripped = Vec::new();
+            for number in split_numbers(&container.message_bag.numbers) {
+                if self.contains(number)? {
+                    stripped.push(number.to_string());
+                } else {
+                    kept.push(number);
+                }
+            }
+
+            if stripped.is_empty() {
+                data.push(container);
+                continue;
+            }
+
+            report.stripped.push(StrippedRecipients {
+                bag_index,
+                numbers: stripped,
+            });
+            if kept.is_empty() {
+                report.dropped_bags.push(bag_index);
+            } else {
+                let numbers = kept.join(",");
+                let mut message_bag = container.message_bag;
+                message_bag.numbers = numbers;
+                data.push(MessageBagContainer { message_bag });
+            }
+        }
+
+        Ok((MessageRequest { data }, report))
+    }
+}
+
+/// `InMemorySuppressionList` keeps suppressed numbers in process memory
+#[derive(Debug, Default)]
+pub struct InMemorySuppressionList {
+    entries: Mutex<BTreeMap<String, SuppressionReason>>,
+}
+
+impl InMemorySuppressionList {
+    pub fn new() -> Self {
+        InMemorySuppressionList::default()
+    }
+}
+
+impl SuppressionList for InMemorySuppressionList {
+    fn contains(&self, number: &str) -> Result<bool, UjumbeSmsError> {
+        let entries = self.entries.lock().expect("suppression list lock poisoned");
+        Ok(entries.contains_key(&normalize_number(number)))
+    }
+
+    fn add(&self, number: &str, reason: SuppressionReason) -> Result<bool, UjumbeSmsError> {
+        let mut entries = self.entries.lock().expect("suppression list lock poisoned");
+        let number = normalize_number(number);
+        if entries.contains_key(&number) {
+            return Ok(false);
+        }
+        entries.insert(number, reason);
+        Ok(true)
+    }
+
+    fn remove(&self, number: &str) -> Result<bool, UjumbeSmsError> {
+        let mut entries = self.entries.lock().expect("suppression list lock poisoned");
+        Ok(entries.remove(&normalize_number(number)).is_some())
+    }
+
+    fn entries(&self) -> Result<Vec<SuppressedNumber>, UjumbeSmsError> {
+        let entries = self.entries.lock().expect("suppression list lock poisoned");
+        Ok(entries
+            .iter()
+            .map(|(number, reason)| SuppressedNumber {
+                number: number.clone(),
+                reason: *reason,
+            })
+            .collect())
+    }
+}
+
+/// `FileSuppressionList` persists suppressed numbers to a text file, one `number,reason` per line.
+/// The file is read once on open; additions are appended and removals rewrite the file.
+#[derive(Debug)]
+pub struct FileSuppressionList {
+    path: PathBuf,
+    entries: Mutex<BTreeMap<String, SuppressionReason>>,
+}
+
+impl FileSuppressionList {
+    /// Opens the list stored at `path`, creating an empty list if the file does not exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, UjumbeSmsError> {
+        let path = path.as_ref().to_path_buf();
+        let mut entries = BTreeMap::new();
+
+        if path.exists() {
+            let reader = BufReader::new(File::open(&path)?);
+            for line in reader.lines() {
+                let line = line?;
+                let mut parts = line.splitn(2, ',');
+                let number = parts.next().unwrap_or_default().trim();
+                if number.is_empty() {
+                    continue;
+                }
+                let reason = parts
+                    .next()
+                    .and_then(|r| r.trim().parse().ok())
+                    .unwrap_or(SuppressionReason::Manual);
+                entries.insert(normalize_number(number), reason);
+            }
+        }
+
+        Ok(FileSuppressionList {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl SuppressionList for FileSuppressionList {
+    fn contains(&self, number: &str) -> Result<bool, UjumbeSmsError> {
+        let entries = self.entries.lock().expect("suppression list lock poisoned");
+        Ok(entries.contains_key(&normalize_number(number)))
+    }
+
+    fn add(&self, number: &str, reason: SuppressionReason) -> Result<bool, UjumbeSmsError> {
+        let mut entries = self.entries.lock().expect("suppression list lock poisoned");
+        let number = normalize_number(number);
+        if entries.contains_key(&number) {
+            return Ok(false);
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{number},{}", reason.as_str())?;
+        entries.insert(number, reason);
+        Ok(true)
+    }
+
+    fn remove(&self, number: &str) -> Result<bool, UjumbeSmsError> {
+        let mut entries = self.entries.lock().expect("suppression list lock poisoned");
+        if entries.remove(&normalize_number(number)).is_none() {
+            return Ok(false);
+        }
+
+        let mut file = File::create(&self.path)?;
+        for (number, reason) in entries.iter() {
+            writeln!(file, "{number},{}", reason.as_str())?;
+        }
+        Ok(true)
+    }
+
+    fn entries(&self) -> Result<Vec<SuppressedNumber>, UjumbeSmsError> {
+        let entries = self.entries.lock().expect("suppression list lock poisoned");
+        Ok(entries
+            .iter()
+            .map(|(number, reason)| SuppressedNumber {
+                number: number.clone(),
+                reason: *reason,
+            })
+            .collect())
+    }
+}
+
+/// `SqliteSuppressionList` stores suppressed numbers in a SQLite table
+#[cfg(feature = "sqlite")]
+pub struct SqliteSuppressionList {
+    conn: Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteSuppressionList {
+    /// Opens (or creates) the database at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, UjumbeSmsError> {
+        Self::from_connection(rusqlite::Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, UjumbeSmsError> {
+        Self::from_connection(rusqlite::Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: rusqlite::Connection) -> Result<Self, UjumbeSmsError> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS suppressions (
+                number TEXT PRIMARY KEY,
+                reason TEXT NOT NULL
+            )",
+            [],
+        )?;
+        Ok(SqliteSuppressionList {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl SuppressionList for SqliteSuppressionList {
+    fn contains(&self, number: &str) -> Result<bool, UjumbeSmsError> {
+        let conn = self.conn.lock().expect("suppression list lock poisoned");
+        let found = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM suppressions WHERE number = ?1)",
+            [normalize_number(number)],
+            |row| row.get(0),
+        )?;
+        Ok(found)
+    }
+
+    fn add(&self, number: &str, reason: SuppressionReason) -> Result<bool, UjumbeSmsError> {
+        let conn = self.conn.lock().expect("suppression list lock poisoned");
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO suppressions (number, reason) VALUES (?1, ?2)",
+            [normalize_number(number).as_str(), reason.as_str()],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    fn remove(&self, number: &str) -> Result<bool, UjumbeSmsError> {
+        let conn = self.conn.lock().expect("suppression list lock poisoned");
+        let removed = conn.execute(
+            "DELETE FROM suppressions WHERE number = ?1",
+            [normalize_number(number)],
+        )?;
+        Ok(removed > 0)
+    }
+
+    fn entries(&self) -> Result<Vec<SuppressedNumber>, UjumbeSmsError> {
+        let conn = self.conn.lock().expect("suppression list lock poisoned");
+        let mut stmt = conn.prepare("SELECT number, reason FROM suppressions ORDER BY number")?;
+        let rows = stmt.query_map([], |row| {
+            let reason: String = row.get(1)?;
+            Ok(SuppressedNumber {
+                number: row.get(0)?,
+                reason: reason.parse().unwrap_or(SuppressionReason::Manual),
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{history, sent};
+
+    #[test]
+    fn test_stop_replies() {
+        assert!(is_stop_reply("stop"));
+        assert!(is_stop_reply(" Stop All "));
+        assert!(is_stop_reply("UNSUBSCRIBE"));
+        assert!(!is_stop_reply("Please stop by the shop"));
+    }
+
+    #[test]
+    fn test_filter_request_strips_suppressed_numbers() {
+        let list = InMemorySuppressionList::new();
+        list.add("+254711111111", SuppressionReason::Manual)
+            .unwrap();
+        list.add("0722222222", SuppressionReason::OptOut).unwrap();
+
+        let mut request = MessageRequest::new();
+        request.add_message_bag(
+            "254711111111,254733333333".to_string(),
+            "First".to_string(),
+            "UjumbeSMS".to_string(),
+        );
+        request.add_message_bag(
+            "254722222222".to_string(),
+            "Second".to_string(),
+            "UjumbeSMS".to_string(),
+        );
+        request.add_message_bag(
+            "254744444444".to_string(),
+            "Third".to_string(),
+            "UjumbeSMS".to_string(),
+        );
+
+        let (filtered, report) = list.filter_request(request).unwrap();
+
+        assert_eq!(filtered.data.len(), 2);
+        assert_eq!(filtered.data[0].message_bag.numbers, "254733333333");
+        assert_eq!(filtered.data[1].message_bag.message, "Third");
+        assert_eq!(report.total_stripped(), 2);
+        assert_eq!(report.dropped_bags, vec![1]);
+        assert_eq!(report.stripped[0].numbers, vec!["254711111111"]);
+    }
+
+    #[test]
+    fn test_absorb_history_and_replies() {
+        let list = InMemorySuppressionList::new();
+        let history = history([
+            sent(1).number("+254711111111"),
+            sent(2).number("+254722222222").status("Blacklisted"),
+            sent(3).number("+254733333333").status("UserBlacklisted"),
+        ]);
+
+        let added = list.absorb_history(&history).unwrap();
+        assert_eq!(added, vec!["254722222222", "254733333333"]);
+        assert!(list.absorb_history(&history).unwrap().is_empty());
+
+        assert!(list.record_reply("0744444444", "STOP").unwrap());
+        assert!(!list.record_reply("0755555555", "Thanks!").unwrap());
+        assert!(list.contains("254744444444").unwrap());
+        assert_eq!(list.entries().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_file_suppression_list_persists() {
+        let path =
+            std::env::temp_dir().join(format!("ujumbe_suppression_{}.txt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        {
+            let list = FileSuppressionList::open(&path).unwrap();
+            assert!(list.add("0711111111", SuppressionReason::OptOut).unwrap());
+            assert!(list.add("0722222222", SuppressionReason::Manual).unwrap());
+            assert!(list.remove("254722222222").unwrap());
+        }
+
+        let reopened = FileSuppressionList::open(&path).unwrap();
+        assert_eq!(
+            reopened.entries().unwrap(),
+            vec![SuppressedNumber {
+                number: "254711111111".to_string(),
+                reason: SuppressionReason::OptOut,
+            }]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_suppression_list() {
+        let list = SqliteSuppressionList::open_in_memory().unwrap();
+        assert!(list
+            .add("+254711111111", SuppressionReason::Blacklisted)
+            .unwrap());
+        assert!(!list.add("0711111111", SuppressionReason::Manual).unwrap());
+        assert!(list.contains("254711111111").unwrap());
+        assert_eq!(
+            list.entries().unwrap()[0].reason,
+            SuppressionReason::Blacklisted
+        );
+        assert!(list.remove("0711111111").unwrap());
+        assert!(!list.contains("254711111111").unwrap());
+    }
+}
@@ -0,0 +1,714 @@
+use crate::client::UjumbeSmsClient;
+use crate::errors::UjumbeSmsError;
+use crate::export::HISTORY_DATE_FORMAT;
+use crate::models::MessageSent;
+use crate::phone::normalize_number;
+use chrono::NaiveDateTime;
+use rusqlite::types::Value;
+use rusqlite::{params, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// `SyncCursor` is where the last completed sync stopped, plus any unfinished backlog
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncCursor {
+    /// Highest `MessageSent.id` seen
+    pub last_id: Option<i64>,
+    /// Latest `MessageSent.updated_at` seen
+    pub last_updated_at: Option<String>,
+    /// Where a run stopped at `max_pages` left off; set until a later run catches up
+    pub resume: Option<SyncResume>,
+}
+
+/// `SyncResume` is the progress of a backlog that is being synced over several capped runs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncResume {
+    /// Next page to fetch
+    pub page: u32,
+    /// Highest `MessageSent.id` seen by the capped runs, saved as the cursor once caught up
+    pub last_id: Option<i64>,
+    /// Latest `MessageSent.updated_at` seen by the capped runs
+    pub last_updated_at: Option<String>,
+}
+
+/// `SyncReport` counts what a sync run changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    pub pages: u32,
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    /// The run stopped at `max_pages` before reaching messages it had already seen, so only
+    /// a resume point was saved
+    pub truncated: bool,
+}
+
+/// `HistoryQuery` filters rows of a `HistoryStore`; unset filters match everything
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    number: Option<String>,
+    status: Option<String>,
+    sender: Option<String>,
+    since: Option<NaiveDateTime>,
+    until: Option<NaiveDateTime>,
+    limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches the number in any format, e.g. `0712345678` matches `254712345678`
+    pub fn with_number(mut self, number: &str) -> Self {
+        self.number = Some(normalize_number(number));
+        self
+    }
+
+    pub fn with_status(mut self, status: impl Into<String>) -> Self {
+        self.status = Some(status.into());
+        self
+    }
+
+    pub fn with_sender(mut self, sender: impl Into<String>) -> Self {
+        self.sender = Some(sender.into());
+        self
+    }
+
+    /// Only rows created in `[since, until)`
+    pub fn with_date_range(
+        mut self,
+        since: Option<NaiveDateTime>,
+        until: Option<NaiveDateTime>,
+    ) -> Self {
+        self.since = since;
+        self.until = until;
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn where_clause(&self) -> (String, Vec<Value>) {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        let mut push = |condition: &str, value: Value| {
+            values.push(value);
+            conditions.push(format!("{condition} ?{}", values.len()));
+        };
+
+        if let Some(number) = &self.number {
+            push("normalized_number =", Value::Text(number.clone()));
+        }
+        if let Some(status) = &self.status {
+            push("status =", Value::Text(status.clone()));
+        }
+        if let Some(sender) = &self.sender {
+            push("sender_id =", Value::Text(sender.clone()));
+        }
+        if let Some(since) = self.since {
+            push(
+                "created_at >=",
+                Value::Text(since.format(HISTORY_DATE_FORMAT).to_string()),
+            );
+        }
+        if let Some(until) = self.until {
+            push(
+                "created_at <",
+                Value::Text(until.format(HISTORY_DATE_FORMAT).to_string()),
+            );
+        }
+
+        if conditions.is_empty() {
+            (String::new(), values)
+        } else {
+            (format!(" WHERE {}", conditions.join(" AND ")), values)
+        }
+    }
+}
+
+/// `HistoryStore` is a local SQLite mirror of the message history
+pub struct HistoryStore {
+    conn: Mutex<rusqlite::Connection>,
+}
+
+impl HistoryStore {
+    /// Opens (or creates) the database at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, UjumbeSmsError> {
+        Self::from_connection(rusqlite::Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, UjumbeSmsError> {
+        Self::from_connection(rusqlite::Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: rusqlite::Connection) -> Result<Self, UjumbeSmsError> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS messages (
+                id INTEGER PRIMARY KEY,
+                request_id INTEGER NOT NULL,
+                number TEXT NOT NULL,
+                normalized_number TEXT NOT NULL,
+                message TEXT NOT NULL,
+                user_id INTEGER NOT NULL,
+                sender_id TEXT NOT NULL,
+                transaction_id TEXT NOT NULL,
+                message_count INTEGER NOT NULL,
+                status TEXT NOT NULL,
+                flag TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                scheduled_date TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS messages_number ON messages (normalized_number);
+            CREATE INDEX IF NOT EXISTS messages_status ON messages (status);
+            CREATE INDEX IF NOT EXISTS messages_sender ON messages (sender_id);
+            CREATE INDEX IF NOT EXISTS messages_created_at ON messages (created_at);
+            CREATE TABLE IF NOT EXISTS sync_state (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );",
+        )?;
+        Ok(HistoryStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// The cursor saved by the last completed sync
+    pub fn cursor(&self) -> Result<SyncCursor, UjumbeSmsError> {
+        let conn = self.conn.lock().expect("history store lock poisoned");
+        let get = |key: &str| {
+            conn.query_row(
+                "SELECT value FROM sync_state WHERE key = ?1",
+                [key],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+        };
+        let resume = match get("resume_page")?.and_then(|page| page.parse().ok()) {
+            Some(page) => Some(SyncResume {
+                page,
+                last_id: get("resume_last_id")?.and_then(|id| id.parse().ok()),
+                last_updated_at: get("resume_last_updated_at")?,
+            }),
+            None => None,
+        };
+        Ok(SyncCursor {
+            last_id: get("last_id")?.and_then(|id| id.parse().ok()),
+            last_updated_at: get("last_updated_at")?,
+            resume,
+        })
+    }
+
+    fn save_cursor(&self, cursor: &SyncCursor) -> Result<(), UjumbeSmsError> {
+        let conn = self.conn.lock().expect("history store lock poisoned");
+        let mut stmt = conn.prepare(
+            "INSERT INTO sync_state (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        )?;
+        if let Some(id) = cursor.last_id {
+            stmt.execute(["last_id", &id.to_string()])?;
+        }
+        if let Some(updated_at) = &cursor.last_updated_at {
+            stmt.execute(["last_updated_at", updated_at])?;
+        }
+        match &cursor.resume {
+            Some(resume) => {
+                stmt.execute(["resume_page", &resume.page.to_string()])?;
+                if let Some(id) = resume.last_id {
+                    stmt.execute(["resume_last_id", &id.to_string()])?;
+                }
+                if let Some(updated_at) = &resume.last_updated_at {
+                    stmt.execute(["resume_last_updated_at", updated_at])?;
+                }
+            }
+            None => {
+                conn.execute(
+                    "DELETE FROM sync_state
+                     WHERE key IN ('resume_page', 'resume_last_id', 'resume_last_updated_at')",
+                    [],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Inserts new rows and updates rows whose status or `updated_at` changed, in one transaction
+    pub fn upsert(&self, messages: &[MessageSent]) -> Result<SyncReport, UjumbeSmsError> {
+        let mut conn = self.conn.lock().expect("history store lock poisoned");
+        let tx = conn.transaction()?;
+        let mut report = SyncReport::default();
+
+        for m in messages {
+            let existing: Option<(String, String)> = tx
+                .query_row(
+                    "SELECT status, updated_at FROM messages WHERE id = ?1",
+                    [m.id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+
+            match existing {
+                Some((status, updated_at)) if status == m.status && updated_at == m.updated_at => {
+                    report.unchanged += 1;
+                    continue;
+                }
+                Some(_) => report.updated += 1,
+                None => report.inserted += 1,
+            }
+
+            tx.execute(
+                "INSERT OR REPLACE INTO messages (
+                    id, request_id, number, normalized_number, message, user_id, sender_id,
+                    transaction_id, message_count, status, flag, created_at, updated_at, scheduled_date
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                params![
+                    m.id,
+                    m.request_id,
+                    m.number,
+                    normalize_number(&m.number),
+                    m.message,
+                    m.user_id,
+                    m.sender_id,
+                    m.transaction_id,
+                    m.message_count,
+                    m.status,
+                    m.flag,
+                    m.created_at,
+                    m.updated_at,
+                    m.scheduled_date,
+                ],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(report)
+    }
+
+    /// Rows matching `query`, newest first
+    pub fn query(&self, query: &HistoryQuery) -> Result<Vec<MessageSent>, UjumbeSmsError> {
+        let (clause, values) = query.where_clause();
+        let limit = query
+            .limit
+            .map(|limit| format!(" LIMIT {limit}"))
+            .unwrap_or_default();
+        let sql = format!(
+            "SELECT id, request_id, number, message, user_id, sender_id, transaction_id,
+                    message_count, status, flag, created_at, updated_at, scheduled_date
+             FROM messages{clause} ORDER BY id DESC{limit}"
+        );
+
+        let conn = self.conn.lock().expect("history store lock poisoned");
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+            Ok(MessageSent {
+                id: row.get(0)?,
+                request_id: row.get(1)?,
+                number: row.get(2)?,
+                message: row.get(3)?,
+                user_id: row.get(4)?,
+                sender_id: row.get(5)?,
+                transaction_id: row.get(6)?,
+                message_count: row.get(7)?,
+                status: row.get(8)?,
+                flag: row.get(9)?,
+                created_at: row.get(10)?,
+                updated_at: row.get(11)?,
+                scheduled_date: row.get(12)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Number of rows matching `query`, ignoring its limit
+    pub fn count(&self, query: &HistoryQuery) -> Result<usize, UjumbeSmsError> {
+        let (clause, values) = query.where_clause();
+        let conn = self.conn.lock().expect("history store lock poisoned");
+        let count: i64 = conn.query_row(
+            &format!("SELECT COUNT(*) FROM messages{clause}"),
+            rusqlite::params_from_iter(values),
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+}
+
+/// `HistorySync` mirrors the remote message history into a `HistoryStore`.
+/// Pages are fetched newest first until a page holds nothing newer than the saved cursor,
+/// so routine syncs only touch the first few pages. The cursor is saved only when a run
+/// reaches that point (or the last page). A run that stops at `max_pages` saves the next
+/// page as a resume point instead, so a backlog deeper than `max_pages` is worked through
+/// over several runs; a run that fails changes nothing.
+pub struct HistorySync<'a> {
+    client: &'a UjumbeSmsClient,
+    store: &'a HistoryStore,
+    max_pages: Option<u32>,
+}
+
+impl<'a> HistorySync<'a> {
+    pub fn new(client: &'a UjumbeSmsClient, store: &'a HistoryStore) -> Self {
+        HistorySync {
+            client,
+            store,
+            max_pages: None,
+        }
+    }
+
+    /// Stops after `max_pages` pages even if older pages may hold changes. Such a run is
+    /// reported as `truncated` and the next run continues from the page after it.
+    pub fn with_max_pages(mut self, max_pages: u32) -> Self {
+        self.max_pages = Some(max_pages);
+        self
+    }
+
+    pub async fn run(&self) -> Result<SyncReport, UjumbeSmsError> {
+        let previous = self.store.cursor()?;
+        let (mut page, mut cursor) = match &previous.resume {
+            Some(resume) => (
+                resume.page,
+                SyncCursor {
+                    last_id: resume.last_id,
+                    last_updated_at: resume.last_updated_at.clone(),
+                    resume: None,
+                },
+            ),
+            None => (
+                1,
+                SyncCursor {
+                    resume: None,
+                    ..previous.clone()
+                },
+            ),
+        };
+        let mut report = SyncReport::default();
+
+        loop {
+            let history = self.client.get_messages_history_page(page).await?;
+            let rows = &history.items.data;
+            report.pages += 1;
+
+            let page_report = self.store.upsert(rows)?;
+            report.inserted += page_report.inserted;
+            report.updated += page_report.updated;
+            report.unchanged += page_report.unchanged;
+
+            let has_news = rows.iter().any(|m| {
+                previous.last_id.is_none_or(|id| m.id > id)
+                    || previous
+                        .last_updated_at
+                        .as_ref()
+                        .is_none_or(|updated_at| m.updated_at > *updated_at)
+            });
+            for m in rows {
+                cursor.last_id = cursor.last_id.max(Some(m.id));
+                if cursor
+                    .last_updated_at
+                    .as_ref()
+                    .is_none_or(|updated_at| m.updated_at > *updated_at)
+                {
+                    cursor.last_updated_at = Some(m.updated_at.clone());
+                }
+            }
+
+            if !has_news || rows.is_empty() || history.items.current_page >= history.items.last_page
+            {
+                break;
+            }
+            if self.max_pages.is_some_and(|max| report.pages >= max) {
+                self.store.save_cursor(&SyncCursor {
+                    resume: Some(SyncResume {
+                        page: page + 1,
+                        last_id: cursor.last_id,
+                        last_updated_at: cursor.last_updated_at,
+                    }),
+                    ..previous
+                })?;
+                report.truncated = true;
+                return Ok(report);
+            }
+            page += 1;
+        }
+
+        self.store.save_cursor(&cursor)?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{history_page, sent};
+    use crate::UjumbeSmsConfig;
+    use mockito::{Matcher, Mock, Server, ServerGuard};
+
+    fn row(id: i64, number: &str, status: &str, updated_at: &str) -> MessageSent {
+        sent(id)
+            .request_id(900 + id)
+            .number(number)
+            .sender(if id % 2 == 0 { "ACME" } else { "UjumbeSMS" })
+            .status(status)
+            .created_at(&format!("2025-07-{id:02} 10:00:00"))
+            .updated_at(updated_at)
+            .build()
+    }
+
+    fn mock_page(
+        server: &mut ServerGuard,
+        page: i32,
+        last_page: i32,
+        rows: Vec<MessageSent>,
+    ) -> Mock {
+        let body = serde_json::to_string(&history_page(page, last_page, rows)).unwrap();
+        server
+            .mock("POST", "/api/messages")
+            .match_query(Matcher::UrlEncoded("page".into(), page.to_string()))
+            .with_status(200)
+            .with_body(body)
+            .create()
+    }
+
+    #[test]
+    fn test_incremental_sync_and_queries() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let client = UjumbeSmsClient::new(config).unwrap();
+            let store = HistoryStore::open_in_memory().unwrap();
+
+            let page1 = mock_page(
+                &mut server,
+                1,
+                3,
+                vec![
+                    row(6, "254766666666", "Pending", "2025-07-06 10:00:00"),
+                    row(
+                        5,
+                        "254755555555",
+                        "DeliveredToTerminal",
+                        "2025-07-05 10:01:00",
+                    ),
+                ],
+            );
+            let page2 = mock_page(
+                &mut server,
+                2,
+                3,
+                vec![
+                    row(
+                        4,
+                        "0712345678",
+                        "DeliveredToTerminal",
+                        "2025-07-04 10:01:00",
+                    ),
+                    row(3, "254712345678", "Blacklisted", "2025-07-03 10:01:00"),
+                ],
+            )
+            .expect(3);
+            let page3 = mock_page(
+                &mut server,
+                3,
+                3,
+                vec![
+                    row(
+                        2,
+                        "254722222222",
+                        "DeliveredToTerminal",
+                        "2025-07-02 10:01:00",
+                    ),
+                    row(
+                        1,
+                        "254711111111",
+                        "DeliveredToTerminal",
+                        "2025-07-01 10:01:00",
+                    ),
+                ],
+            )
+            .expect(1);
+
+            let report = HistorySync::new(&client, &store).run().await.unwrap();
+            assert_eq!(
+                report,
+                SyncReport {
+                    pages: 3,
+                    inserted: 6,
+                    updated: 0,
+                    unchanged: 0,
+                    truncated: false
+                }
+            );
+            assert_eq!(
+                store.cursor().unwrap(),
+                SyncCursor {
+                    last_id: Some(6),
+                    last_updated_at: Some("2025-07-06 10:00:00".to_string()),
+                    resume: None,
+                }
+            );
+
+            // A new message arrives and message 6 is delivered
+            page1.remove();
+            let page1 = mock_page(
+                &mut server,
+                1,
+                4,
+                vec![
+                    row(7, "254777777777", "Pending", "2025-07-07 10:00:00"),
+                    row(
+                        6,
+                        "254766666666",
+                        "DeliveredToTerminal",
+                        "2025-07-07 10:00:05",
+                    ),
+                ],
+            );
+            let report = HistorySync::new(&client, &store).run().await.unwrap();
+            assert_eq!(
+                report,
+                SyncReport {
+                    pages: 2,
+                    inserted: 1,
+                    updated: 1,
+                    unchanged: 2,
+                    truncated: false
+                }
+            );
+            page3.assert();
+
+            assert_eq!(store.count(&HistoryQuery::new()).unwrap(), 7);
+            let by_number = store
+                .query(&HistoryQuery::new().with_number("+254 712 345 678"))
+                .unwrap();
+            assert_eq!(
+                by_number.iter().map(|m| m.id).collect::<Vec<_>>(),
+                vec![4, 3]
+            );
+            let delivered_by_acme = store
+                .query(
+                    &HistoryQuery::new()
+                        .with_status("DeliveredToTerminal")
+                        .with_sender("ACME"),
+                )
+                .unwrap();
+            assert_eq!(
+                delivered_by_acme.iter().map(|m| m.id).collect::<Vec<_>>(),
+                vec![6, 4, 2]
+            );
+            let first_week = HistoryQuery::new()
+                .with_date_range(
+                    NaiveDateTime::parse_from_str("2025-07-02 00:00:00", HISTORY_DATE_FORMAT).ok(),
+                    NaiveDateTime::parse_from_str("2025-07-05 00:00:00", HISTORY_DATE_FORMAT).ok(),
+                )
+                .with_limit(2);
+            assert_eq!(store.count(&first_week).unwrap(), 3);
+            assert_eq!(
+                store
+                    .query(&first_week)
+                    .unwrap()
+                    .iter()
+                    .map(|m| m.id)
+                    .collect::<Vec<_>>(),
+                vec![4, 3]
+            );
+
+            // Two more messages arrive; a capped run that has not caught up only saves a resume point
+            page1.remove();
+            let _page1 = mock_page(
+                &mut server,
+                1,
+                4,
+                vec![
+                    row(9, "254799999999", "Pending", "2025-07-09 10:00:00"),
+                    row(8, "254788888888", "Pending", "2025-07-08 10:00:00"),
+                ],
+            );
+            let report = HistorySync::new(&client, &store)
+                .with_max_pages(1)
+                .run()
+                .await
+                .unwrap();
+            assert!(report.truncated);
+            assert_eq!(store.cursor().unwrap().last_id, Some(7));
+            let report = HistorySync::new(&client, &store).run().await.unwrap();
+            assert_eq!((report.pages, report.truncated), (1, false));
+            assert_eq!(store.cursor().unwrap().last_id, Some(9));
+            page2.assert();
+        });
+    }
+
+    #[test]
+    fn test_capped_runs_work_through_a_deep_backlog() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let config =
+                UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                    .with_base_url(url);
+            let client = UjumbeSmsClient::new(config).unwrap();
+            let store = HistoryStore::open_in_memory().unwrap();
+
+            let pages = [
+                mock_page(
+                    &mut server,
+                    1,
+                    3,
+                    vec![row(3, "254733333333", "Pending", "2025-07-03 10:00:00")],
+                ),
+                mock_page(
+                    &mut server,
+                    2,
+                    3,
+                    vec![row(2, "254722222222", "Pending", "2025-07-02 10:00:00")],
+                ),
+                mock_page(
+                    &mut server,
+                    3,
+                    3,
+                    vec![row(1, "254711111111", "Pending", "2025-07-01 10:00:00")],
+                ),
+            ];
+            let sync = HistorySync::new(&client, &store).with_max_pages(1);
+
+            for (run, next_page) in [(1, 2), (2, 3)] {
+                let report = sync.run().await.unwrap();
+                assert_eq!((report.pages, report.inserted), (1, 1), "run {run}");
+                assert!(report.truncated, "run {run}");
+                let cursor = store.cursor().unwrap();
+                assert_eq!(cursor.last_id, None, "run {run}");
+                assert_eq!(
+                    cursor.resume,
+                    Some(SyncResume {
+                        page: next_page,
+                        last_id: Some(3),
+                        last_updated_at: Some("2025-07-03 10:00:00".to_string()),
+                    }),
+                    "run {run}"
+                );
+            }
+
+            let report = sync.run().await.unwrap();
+            assert_eq!((report.pages, report.inserted), (1, 1));
+            assert!(!report.truncated);
+            assert_eq!(
+                store.cursor().unwrap(),
+                SyncCursor {
+                    last_id: Some(3),
+                    last_updated_at: Some("2025-07-03 10:00:00".to_string()),
+                    resume: None,
+                }
+            );
+            assert_eq!(store.count(&HistoryQuery::new()).unwrap(), 3);
+            for page in pages {
+                page.assert();
+            }
+        });
+    }
+}
@@ -0,0 +1,502 @@
+//! Instrumentation shared by every `UjumbeSmsClient` call, and the names of the metrics it emits.
+//! With the `tracing` feature each call runs inside a span, and with the `metrics` feature each
+//! call updates the counters, histograms and gauge named below; without either this compiles to
+//! timing only. Neither carries message bodies or credentials, and phone numbers are masked.
+
+use crate::client::ApiEndpoint;
+use crate::errors::UjumbeSmsError;
+use crate::hooks::HookResponse;
+use crate::models::{
+    BalanceApiResponse, MessageHistoryApiResponse, MessageRequest, MessagingApiResponse, StatusInfo,
+};
+use crate::sender::SenderSubstitution;
+use reqwest::StatusCode;
+use std::future::Future;
+use std::time::Instant;
+
+/// Maximum number of masked recipients listed on a span
+#[cfg(feature = "tracing")]
+const MAX_LOGGED_NUMBERS: usize = 10;
+
+/// Counter of completed calls, labelled by `endpoint` and `outcome` (`success` or `error`)
+pub const REQUESTS_TOTAL: &str = "ujumbe_sms_requests_total";
+/// Histogram of call latency in seconds, labelled by `endpoint`
+pub const REQUEST_DURATION_SECONDS: &str = "ujumbe_sms_request_duration_seconds";
+/// Counter of message bags accepted by the messaging endpoint
+pub const MESSAGES_SENT_TOTAL: &str = "ujumbe_sms_messages_sent_total";
+/// Counter of recipients accepted by the messaging endpoint
+pub const RECIPIENTS_TOTAL: &str = "ujumbe_sms_recipients_total";
+/// Counter of credits deducted by the messaging endpoint
+pub const CREDITS_DEDUCTED_TOTAL: &str = "ujumbe_sms_credits_deducted_total";
+/// Counter of failed calls, labelled by `endpoint`, `kind` (see `UjumbeSmsError::kind`) and `status_code`
+pub const ERRORS_TOTAL: &str = "ujumbe_sms_errors_total";
+/// Gauge of the latest credit balance reported by the API
+pub const CREDIT_BALANCE: &str = "ujumbe_sms_credit_balance";
+/// Counter of message bags whose sender was replaced by the client's `SenderRegistry` default
+pub const SENDER_SUBSTITUTIONS_TOTAL: &str = "ujumbe_sms_sender_substitutions_total";
+
+/// Registers units and descriptions for every UjumbeSMS metric with the installed recorder
+#[cfg(feature = "metrics")]
+pub fn describe_metrics() {
+    use metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+
+    describe_counter!(
+        REQUESTS_TOTAL,
+        "UjumbeSMS API calls by endpoint and outcome"
+    );
+    describe_histogram!(
+        REQUEST_DURATION_SECONDS,
+        Unit::Seconds,
+        "UjumbeSMS API call latency by endpoint"
+    );
+    describe_counter!(MESSAGES_SENT_TOTAL, "Message bags accepted by UjumbeSMS");
+    describe_counter!(RECIPIENTS_TOTAL, "Recipients accepted by UjumbeSMS");
+    describe_counter!(CREDITS_DEDUCTED_TOTAL, "Credits deducted by UjumbeSMS");
+    describe_counter!(
+        ERRORS_TOTAL,
+        "Failed UjumbeSMS API calls by error kind and status code"
+    );
+    describe_gauge!(CREDIT_BALANCE, "Latest UjumbeSMS credit balance");
+    describe_counter!(
+        SENDER_SUBSTITUTIONS_TOTAL,
+        "Message bags sent with the default sender instead of an unapproved one"
+    );
+}
+
+/// Reports the senders the client's `SenderRegistry` replaced before a send
+pub(crate) fn report_sender_substitutions(substitutions: &[SenderSubstitution]) {
+    #[cfg(feature = "tracing")]
+    for substitution in substitutions {
+        tracing::warn!(
+            bag = substitution.index,
+            requested = %substitution.requested,
+            used = %substitution.used,
+            "UjumbeSMS sender replaced by the registry default"
+        );
+    }
+
+    #[cfg(feature = "metrics")]
+    if !substitutions.is_empty() {
+        metrics::counter!(SENDER_SUBSTITUTIONS_TOTAL).increment(substitutions.len() as u64);
+    }
+
+    #[cfg(not(any(feature = "tracing", feature = "metrics")))]
+    let _ = substitutions;
+}
+
+/// Gives telemetry access to the parts of an API response it reports on
+pub(crate) trait ApiResponse {
+    fn hook_response(&self) -> HookResponse<'_>;
+
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    fn status_info(&self) -> &StatusInfo;
+
+    /// `(recipients, credits deducted)` for messaging responses
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    fn delivery(&self) -> Option<(u64, u64)> {
+        None
+    }
+
+    /// The credit balance reported alongside the response, if any
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    fn available_credits(&self) -> Option<f64> {
+        None
+    }
+}
+
+impl ApiResponse for MessagingApiResponse {
+    fn hook_response(&self) -> HookResponse<'_> {
+        HookResponse::Messaging(self)
+    }
+
+    fn status_info(&self) -> &StatusInfo {
+        &self.status
+    }
+
+    fn delivery(&self) -> Option<(u64, u64)> {
+        self.meta.as_ref().map(|meta| {
+            (
+                meta.recipients.max(0) as u64,
+                meta.credits_deducted.max(0) as u64,
+            )
+        })
+    }
+
+    fn available_credits(&self) -> Option<f64> {
+        self.meta
+            .as_ref()
+            .and_then(|meta| meta.available_credits.trim().parse().ok())
+    }
+}
+
+impl ApiResponse for BalanceApiResponse {
+    fn hook_response(&self) -> HookResponse<'_> {
+        HookResponse::Balance(self)
+    }
+
+    fn status_info(&self) -> &StatusInfo {
+        &self.status
+    }
+
+    fn available_credits(&self) -> Option<f64> {
+        self.meta.as_ref().map(|meta| f64::from(meta.credits))
+    }
+}
+
+impl ApiResponse for MessageHistoryApiResponse {
+    fn hook_response(&self) -> HookResponse<'_> {
+        HookResponse::MessageHistory(self)
+    }
+
+    fn status_info(&self) -> &StatusInfo {
+        &self.status
+    }
+}
+
+/// `CallTelemetry` follows one client call from start to finish
+pub(crate) struct CallTelemetry {
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    endpoint: &'static str,
+    #[cfg_attr(not(feature = "metrics"), allow(dead_code))]
+    bags: usize,
+    started: Instant,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl CallTelemetry {
+    pub(crate) fn start(endpoint: &ApiEndpoint, request: Option<&MessageRequest>) -> Self {
+        #[cfg(feature = "tracing")]
+        let span = {
+            let (recipients, bags, numbers) = match request {
+                Some(request) => (
+                    request.recipient_count(),
+                    request.data.len(),
+                    masked_numbers(request),
+                ),
+                None => (0, 0, String::new()),
+            };
+            tracing::info_span!(
+                "ujumbe_sms.request",
+                endpoint = endpoint.as_str(),
+                recipients,
+                bags,
+                numbers = %numbers,
+                http.status = tracing::field::Empty,
+                status_code = tracing::field::Empty,
+                latency_ms = tracing::field::Empty,
+                retry_attempt = tracing::field::Empty,
+            )
+        };
+        #[cfg(not(feature = "tracing"))]
+        let _ = request;
+
+        CallTelemetry {
+            endpoint: endpoint.as_str(),
+            bags: request.map_or(0, |request| request.data.len()),
+            started: Instant::now(),
+            #[cfg(feature = "tracing")]
+            span,
+        }
+    }
+
+    /// Runs `future` inside the call's span
+    pub(crate) async fn instrument<F: Future>(&self, future: F) -> F::Output {
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::Instrument;
+            future.instrument(self.span.clone()).await
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            future.await
+        }
+    }
+
+    /// Records the HTTP status and the attempt number of a transport round trip
+    pub(crate) fn http_response(&self, status: StatusCode, attempt: u32) {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.record("http.status", status.as_u16());
+            self.span.record("retry_attempt", attempt);
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = (status, attempt);
+    }
+
+    /// Records the outcome of the call
+    pub(crate) fn finish<T: ApiResponse>(&self, result: &Result<T, UjumbeSmsError>) {
+        let latency = self.started.elapsed();
+
+        #[cfg(feature = "tracing")]
+        {
+            self.span
+                .record("latency_ms", latency.as_secs_f64() * 1000.0);
+            let _entered = self.span.enter();
+            match result {
+                Ok(response) => {
+                    let status = response.status_info();
+                    self.span.record("status_code", status.code.as_str());
+                    tracing::info!(status_type = %status.r#type, "UjumbeSMS request completed");
+                }
+                Err(error) => {
+                    tracing::warn!(error = %error, "UjumbeSMS request failed");
+                }
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        self.record_metrics(latency, result);
+
+        #[cfg(not(any(feature = "tracing", feature = "metrics")))]
+        let _ = (latency, result);
+    }
+
+    #[cfg(feature = "metrics")]
+    fn record_metrics<T: ApiResponse>(
+        &self,
+        latency: std::time::Duration,
+        result: &Result<T, UjumbeSmsError>,
+    ) {
+        use metrics::{counter, gauge, histogram};
+
+        let endpoint = self.endpoint;
+        histogram!(REQUEST_DURATION_SECONDS, "endpoint" => endpoint).record(latency.as_secs_f64());
+
+        match result {
+            Ok(response) => {
+                counter!(REQUESTS_TOTAL, "endpoint" => endpoint, "outcome" => "success")
+                    .increment(1);
+                if let Some((recipients, credits)) = response.delivery() {
+                    counter!(MESSAGES_SENT_TOTAL).increment(self.bags as u64);
+                    counter!(RECIPIENTS_TOTAL).increment(recipients);
+                    counter!(CREDITS_DEDUCTED_TOTAL).increment(credits);
+                }
+                if let Some(credits) = response.available_credits() {
+                    gauge!(CREDIT_BALANCE).set(credits);
+                }
+            }
+            Err(error) => {
+                counter!(REQUESTS_TOTAL, "endpoint" => endpoint, "outcome" => "error").increment(1);
+                // `ApiError` codes look like "402 Payment Required"; only the number is a useful label
+                let status_code = match error {
+                    UjumbeSmsError::ApiError(code, _) => code
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    _ => "none".to_string(),
+                };
+                counter!(
+                    ERRORS_TOTAL,
+                    "endpoint" => endpoint,
+                    "kind" => error.kind(),
+                    "status_code" => status_code
+                )
+                .increment(1);
+            }
+        }
+    }
+}
+
+/// Comma separated masked recipients, truncated to `MAX_LOGGED_NUMBERS`
+#[cfg(feature = "tracing")]
+fn masked_numbers(request: &MessageRequest) -> String {
+    let mut numbers = request
+        .data
+        .iter()
+        .flat_map(|c| crate::phone::split_numbers(&c.message_bag.numbers))
+        .map(crate::phone::mask_number);
+    let mut masked: Vec<String> = numbers.by_ref().take(MAX_LOGGED_NUMBERS).collect();
+    let remaining = numbers.count();
+    if remaining > 0 {
+        masked.push(format!("+{remaining} more"));
+    }
+    masked.join(",")
+}
+
+#[cfg(all(test, any(feature = "tracing", feature = "metrics")))]
+mod tests {
+    use crate::{UjumbeSmsClient, UjumbeSmsConfig};
+    use mockito::Server;
+
+    #[cfg(feature = "tracing")]
+    #[derive(Clone, Default)]
+    struct CapturedLogs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    #[cfg(feature = "tracing")]
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_spans_mask_numbers_and_omit_secrets() {
+        use crate::test_support::messaging_body;
+
+        let mut server = Server::new();
+        let url = server.url();
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+            .finish();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            rt.block_on(async {
+                let _mock = server
+                    .mock("POST", "/api/messaging")
+                    .with_status(200)
+                    .with_body(messaging_body(2))
+                    .create();
+
+                let config = UjumbeSmsConfig::new(
+                    "secret_api_key".to_string(),
+                    "test@email.com".to_string(),
+                )
+                .with_base_url(url);
+                let registry =
+                    crate::SenderRegistry::new([]).with_default_sender(crate::SenderId::default());
+                let client = UjumbeSmsClient::builder(config)
+                    .with_sender_registry(registry)
+                    .build()
+                    .unwrap();
+                client
+                    .send_single_message(
+                        "254712345678,254798765432",
+                        "Top secret body",
+                        "UjumbeSMS",
+                    )
+                    .await
+                    .unwrap();
+                client
+                    .send_single_message("254712345678", "Top secret body", "ACME")
+                    .await
+                    .unwrap();
+            });
+        });
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(
+            output.contains("bag=0 requested=ACME used=UjumbeSMS"),
+            "{output}"
+        );
+        assert!(output.contains("endpoint=\"/api/messaging\""), "{output}");
+        assert!(output.contains("recipients=2"), "{output}");
+        assert!(output.contains("254712****78,254798****32"), "{output}");
+        assert!(output.contains("status_code=\"1008\""), "{output}");
+        assert!(output.contains("http.status=200"), "{output}");
+        assert!(!output.contains("254712345678"), "{output}");
+        assert!(!output.contains("Top secret body"), "{output}");
+        assert!(!output.contains("secret_api_key"), "{output}");
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics_recorded_per_call() {
+        use crate::test_support::messaging_body_with_credits;
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+        use metrics_util::MetricKind;
+
+        let mut server = Server::new();
+        let url = server.url();
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        metrics::with_local_recorder(&recorder, || {
+            rt.block_on(async {
+                let _sent = server
+                    .mock("POST", "/api/messaging")
+                    .with_status(200)
+                    .with_body(
+                        messaging_body_with_credits(2, 4, 6608),
+                    )
+                    .create();
+                let _balance = server
+                    .mock("POST", "/api/balance")
+                    .with_status(402)
+                    .with_body(r#"{"status": {"code": "1006", "type": "error", "description": "Insufficient credits"}}"#)
+                    .create();
+
+                let config =
+                    UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+                        .with_base_url(url);
+                let client = UjumbeSmsClient::new(config).unwrap();
+                client
+                    .send_single_message("254712345678,254798765432", "Hello", "UjumbeSMS")
+                    .await
+                    .unwrap();
+                assert!(client.balance().await.is_err());
+            });
+        });
+
+        let metrics: Vec<_> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| (key, value))
+            .collect();
+        let value = |kind: MetricKind, name: &str, labels: &[(&str, &str)]| {
+            metrics
+                .iter()
+                .find(|(key, _)| {
+                    key.kind() == kind
+                        && key.key().name() == name
+                        && labels.iter().all(|(k, v)| {
+                            key.key()
+                                .labels()
+                                .any(|label| label.key() == *k && label.value() == *v)
+                        })
+                })
+                .map(|(_, value)| value)
+        };
+
+        assert_eq!(
+            value(MetricKind::Counter, super::RECIPIENTS_TOTAL, &[]),
+            Some(&DebugValue::Counter(2))
+        );
+        assert_eq!(
+            value(MetricKind::Counter, super::CREDITS_DEDUCTED_TOTAL, &[]),
+            Some(&DebugValue::Counter(4))
+        );
+        assert_eq!(
+            value(MetricKind::Counter, super::MESSAGES_SENT_TOTAL, &[]),
+            Some(&DebugValue::Counter(1))
+        );
+        assert_eq!(
+            value(MetricKind::Gauge, super::CREDIT_BALANCE, &[]),
+            Some(&DebugValue::Gauge(6608.0.into()))
+        );
+        assert_eq!(
+            value(
+                MetricKind::Counter,
+                super::ERRORS_TOTAL,
+                &[
+                    ("endpoint", "/api/balance"),
+                    ("kind", "api"),
+                    ("status_code", "402")
+                ]
+            ),
+            Some(&DebugValue::Counter(1))
+        );
+        assert!(matches!(
+            value(
+                MetricKind::Histogram,
+                super::REQUEST_DURATION_SECONDS,
+                &[("endpoint", "/api/messaging")]
+            ),
+            Some(DebugValue::Histogram(samples)) if samples.len() == 1
+        ));
+    }
+}
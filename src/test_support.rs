@@ -0,0 +1,182 @@
+//! Fixtures shared by the unit tests
+
+use crate::client::UjumbeSmsClient;
+use crate::models::{MessageHistoryApiResponse, MessageRequest, MessageSent};
+use crate::transport::HttpRequest;
+use crate::UjumbeSmsConfig;
+use std::sync::{Arc, Mutex};
+
+/// Body of a successful messaging response charging one credit per recipient
+pub fn messaging_body(recipients: i32) -> String {
+    messaging_body_with_credits(recipients, recipients, 6608)
+}
+
+/// Body of a successful messaging response with explicit credit figures
+pub fn messaging_body_with_credits(
+    recipients: i32,
+    credits_deducted: i32,
+    available_credits: i32,
+) -> String {
+    serde_json::json!({
+        "status": { "code": "1008", "type": "success", "description": "Your messages have been queued" },
+        "meta": {
+            "recipients": recipients,
+            "credits_deducted": credits_deducted,
+            "available_credits": available_credits.to_string(),
+            "user_email": "test@email.com",
+            "date_time": { "date": "20150815 18:19:47", "timezone_type": 3, "timezone": "Africa/Nairobi" }
+        }
+    })
+    .to_string()
+}
+
+/// Body of a successful messaging response dated `date`, e.g. `20150815 18:19:47`
+pub fn messaging_body_at(recipients: i32, date: &str) -> String {
+    let mut body: serde_json::Value = serde_json::from_str(&messaging_body(recipients)).unwrap();
+    body["meta"]["date_time"]["date"] = date.into();
+    body.to_string()
+}
+
+/// Body of a successful balance response
+pub fn balance_body(credits: i32) -> String {
+    serde_json::json!({
+        "status": { "code": "1008", "type": "success", "description": "Balance inquiry" },
+        "meta": {
+            "user": "test@email.com",
+            "credits": credits,
+            "rate": 1,
+            "date_time": { "date": "20150815 18:19:47", "timezone_type": 3, "timezone": "Africa/Nairobi" }
+        }
+    })
+    .to_string()
+}
+
+/// A client for `base_url` that records the first bag's message of every messaging request
+pub fn recording_client(base_url: String) -> (UjumbeSmsClient, Arc<Mutex<Vec<String>>>) {
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    let record = messages.clone();
+    let config = UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+        .with_base_url(base_url);
+    let client = UjumbeSmsClient::builder(config)
+        .layer(tower::util::MapRequestLayer::new(
+            move |request: HttpRequest| {
+                if let Some(body) = request.body.as_deref() {
+                    if let Ok(body) = serde_json::from_slice::<MessageRequest>(body) {
+                        record
+                            .lock()
+                            .unwrap()
+                            .push(body.data[0].message_bag.message.clone());
+                    }
+                }
+                request
+            },
+        ))
+        .build()
+        .unwrap();
+    (client, messages)
+}
+
+/// `SentBuilder` builds a history row. Unset fields are those of a delivered one-credit
+/// "Hello" from UjumbeSMS to 254712345678.
+pub struct SentBuilder(MessageSent);
+
+/// A history row with `id`, see `SentBuilder`
+pub fn sent(id: i64) -> SentBuilder {
+    SentBuilder(MessageSent {
+        id,
+        request_id: 1,
+        number: "254712345678".to_string(),
+        message: "Hello".to_string(),
+        user_id: 3062,
+        sender_id: "UjumbeSMS".to_string(),
+        transaction_id: format!("t{id}"),
+        message_count: 1,
+        status: "DeliveredToTerminal".to_string(),
+        flag: "API|".to_string(),
+        created_at: "2025-07-20 18:18:11".to_string(),
+        updated_at: "2025-07-20 18:18:11".to_string(),
+        scheduled_date: "2025-07-20 18:18:11".to_string(),
+    })
+}
+
+impl SentBuilder {
+    pub fn request_id(mut self, request_id: i64) -> Self {
+        self.0.request_id = request_id;
+        self
+    }
+
+    pub fn number(mut self, number: &str) -> Self {
+        self.0.number = number.to_string();
+        self
+    }
+
+    pub fn message(mut self, message: &str) -> Self {
+        self.0.message = message.to_string();
+        self
+    }
+
+    pub fn sender(mut self, sender: &str) -> Self {
+        self.0.sender_id = sender.to_string();
+        self
+    }
+
+    pub fn status(mut self, status: &str) -> Self {
+        self.0.status = status.to_string();
+        self
+    }
+
+    pub fn message_count(mut self, message_count: i32) -> Self {
+        self.0.message_count = message_count;
+        self
+    }
+
+    /// Sets `created_at`, `updated_at` and `scheduled_date`
+    pub fn created_at(mut self, created_at: &str) -> Self {
+        self.0.created_at = created_at.to_string();
+        self.0.updated_at = created_at.to_string();
+        self.0.scheduled_date = created_at.to_string();
+        self
+    }
+
+    pub fn updated_at(mut self, updated_at: &str) -> Self {
+        self.0.updated_at = updated_at.to_string();
+        self
+    }
+
+    pub fn build(self) -> MessageSent {
+        self.0
+    }
+}
+
+impl From<SentBuilder> for MessageSent {
+    fn from(builder: SentBuilder) -> Self {
+        builder.0
+    }
+}
+
+/// Page `page` of `last_page` of the message history, holding `rows`
+pub fn history_page<I>(page: i32, last_page: i32, rows: I) -> MessageHistoryApiResponse
+where
+    I: IntoIterator,
+    I::Item: Into<MessageSent>,
+{
+    let data: Vec<MessageSent> = rows.into_iter().map(Into::into).collect();
+    serde_json::from_value(serde_json::json!({
+        "status": { "code": "1008", "type": "success", "description": "Query Success" },
+        "meta": null,
+        "items": {
+            "total": data.len(), "per_page": 50, "current_page": page, "last_page": last_page,
+            "next_page_url": null, "prev_page_url": null, "from": 1, "to": data.len(), "data": data
+        }
+    }))
+    .unwrap()
+}
+
+/// A single-page message history holding `rows`
+pub fn history<I>(rows: I) -> MessageHistoryApiResponse
+where
+    I: IntoIterator,
+    I::Item: Into<MessageSent>,
+{
+    history_page(1, 1, rows)
+}
@@ -0,0 +1,238 @@
+//! The HTTP layer of `UjumbeSmsClient`, expressed as a `tower::Service`.
+//! Every API call becomes one `HttpRequest` sent through the service stack; custom `Layer`s
+//! registered on `UjumbeSmsClientBuilder` wrap the reqwest-backed service at the bottom of it.
+
+use crate::errors::UjumbeSmsError;
+use reqwest::header::HeaderMap;
+use reqwest::{Client as ReqwestClient, Method, StatusCode};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::util::BoxCloneSyncService;
+use tower::Service;
+
+/// Error type of the service stack; middleware errors are surfaced as `UjumbeSmsError::TransportError`
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The type-erased service stack a client sends its requests through
+pub type HttpService = BoxCloneSyncService<HttpRequest, HttpResponse, BoxError>;
+
+/// `HttpRequest` is one UjumbeSMS API request, with authentication headers already attached.
+/// It is cheap to clone, so it works with `tower::retry`.
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    /// Full request URL, i.e. the configured base URL followed by `path`
+    pub url: String,
+    /// Endpoint path including any query string, e.g. `/api/messages?page=2`
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: Option<Vec<u8>>,
+    /// Shared across clones so retries of one call are counted together
+    attempts: Arc<AtomicU32>,
+}
+
+impl HttpRequest {
+    pub fn new(
+        method: Method,
+        url: String,
+        path: String,
+        headers: HeaderMap,
+        body: Option<Vec<u8>>,
+    ) -> Self {
+        HttpRequest {
+            method,
+            url,
+            path,
+            headers,
+            body,
+            attempts: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    /// Number of times this request (or a clone of it) has reached the transport
+    pub fn attempts(&self) -> u32 {
+        self.attempts.load(Ordering::SeqCst)
+    }
+
+    /// Marks the start of a transport round trip
+    pub(crate) fn begin_attempt(&self) {
+        self.attempts.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// `HttpResponse` is the status and body text of an API response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+/// `ReqwestService` is the bottom of every service stack and performs the actual HTTP exchange
+#[derive(Debug, Clone)]
+pub struct ReqwestService {
+    client: ReqwestClient,
+}
+
+impl ReqwestService {
+    pub fn new(client: ReqwestClient) -> Self {
+        ReqwestService { client }
+    }
+}
+
+impl Service<HttpRequest> for ReqwestService {
+    type Response = HttpResponse;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<HttpResponse, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: HttpRequest) -> Self::Future {
+        let client = self.client.clone();
+        Box::pin(async move {
+            request.begin_attempt();
+            let mut builder = client
+                .request(request.method, &request.url)
+                .headers(request.headers);
+            if let Some(body) = request.body {
+                builder = builder.body(body);
+            }
+
+            let response = builder.send().await?;
+            let status = response.status();
+            let body = response.text().await?;
+            Ok(HttpResponse { status, body })
+        })
+    }
+}
+
+/// Turns a service stack error back into the most specific `UjumbeSmsError`
+pub(crate) fn into_client_error(error: BoxError) -> UjumbeSmsError {
+    let error = match error.downcast::<UjumbeSmsError>() {
+        Ok(error) => return *error,
+        Err(error) => error,
+    };
+    match error.downcast::<reqwest::Error>() {
+        Ok(error) => UjumbeSmsError::NetworkError(*error),
+        Err(error) => UjumbeSmsError::TransportError(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::balance_body;
+    use crate::{UjumbeSmsClient, UjumbeSmsConfig};
+    use mockito::Server;
+    use reqwest::header::HeaderValue;
+    use std::time::Duration;
+
+    /// Retries 5xx responses up to `remaining` more times
+    #[derive(Clone)]
+    struct RetryServerErrors {
+        remaining: usize,
+    }
+
+    impl tower::retry::Policy<HttpRequest, HttpResponse, BoxError> for RetryServerErrors {
+        type Future = std::future::Ready<()>;
+
+        fn retry(
+            &mut self,
+            _request: &mut HttpRequest,
+            result: &mut Result<HttpResponse, BoxError>,
+        ) -> Option<Self::Future> {
+            match result {
+                Ok(response) if response.status.is_server_error() && self.remaining > 0 => {
+                    self.remaining -= 1;
+                    Some(std::future::ready(()))
+                }
+                _ => None,
+            }
+        }
+
+        fn clone_request(&mut self, request: &HttpRequest) -> Option<HttpRequest> {
+            Some(request.clone())
+        }
+    }
+
+    fn config(url: String) -> UjumbeSmsConfig {
+        UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+            .with_base_url(url)
+    }
+
+    #[test]
+    fn test_custom_layers_wrap_transport() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let failure = server
+                .mock("POST", "/api/balance")
+                .with_status(503)
+                .expect(1)
+                .create();
+            let success = server
+                .mock("POST", "/api/balance")
+                .match_header("x-audit", "billing")
+                .with_status(200)
+                .with_body(balance_body(10))
+                .expect(1)
+                .create();
+
+            let client = UjumbeSmsClient::builder(config(url))
+                .layer(tower::retry::RetryLayer::new(RetryServerErrors {
+                    remaining: 2,
+                }))
+                .layer(tower::util::MapRequestLayer::new(
+                    |mut request: HttpRequest| {
+                        request
+                            .headers
+                            .insert("x-audit", HeaderValue::from_static("billing"));
+                        request
+                    },
+                ))
+                .build()
+                .unwrap();
+
+            let response = client.balance().await.unwrap();
+            assert_eq!(response.meta.unwrap().credits, 10);
+            failure.assert();
+            success.assert();
+        });
+    }
+
+    #[test]
+    fn test_middleware_errors_surface_as_transport_errors() {
+        let mut server = Server::new();
+        let url = server.url();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        rt.block_on(async {
+            let _slow = server
+                .mock("POST", "/api/balance")
+                .with_status(200)
+                .with_chunked_body(|w| {
+                    std::thread::sleep(Duration::from_millis(300));
+                    w.write_all(balance_body(10).as_bytes())
+                })
+                .create();
+
+            let client = UjumbeSmsClient::builder(config(url))
+                .layer(tower::timeout::TimeoutLayer::new(Duration::from_millis(50)))
+                .build()
+                .unwrap();
+
+            match client.balance().await {
+                Err(UjumbeSmsError::TransportError(error)) => {
+                    assert!(error.is::<tower::timeout::error::Elapsed>())
+                }
+                other => panic!("Expected TransportError, got {other:?}"),
+            }
+        });
+    }
+}
@@ -0,0 +1,510 @@
+//! A local mock of the UjumbeSMS API for integration tests.
+//!
+//! `MockServer` implements `/api/messaging`, `/api/balance` and `/api/messages` with the same
+//! response shapes as the real service. It deducts credits per recipient and SMS segment, keeps
+//! every sent message in a store that feeds the paginated history, checks the
+//! `X-Authorization`/`Email` headers and can inject faults such as latency, error statuses
+//! and malformed bodies.
+
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use serde::Deserialize;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use ujumbe_sms::client::ApiEndpoint;
+use ujumbe_sms::models::{Items, MessageSent};
+use ujumbe_sms::phone::{normalize_number, split_numbers};
+use ujumbe_sms::MessageRequest;
+
+/// `MockConfig` sets the account the mock server accepts and its starting state
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    pub api_key: String,
+    pub email: String,
+    pub credits: i64,
+    pub rate: i32,
+    /// History page size
+    pub per_page: usize,
+    /// Status given to newly sent messages
+    pub delivery_status: String,
+}
+
+impl MockConfig {
+    pub fn new(api_key: &str, email: &str) -> Self {
+        MockConfig {
+            api_key: api_key.to_string(),
+            email: email.to_string(),
+            credits: 1000,
+            rate: 1,
+            per_page: 50,
+            delivery_status: "DeliveredToTerminal".to_string(),
+        }
+    }
+
+    pub fn with_credits(mut self, credits: i64) -> Self {
+        self.credits = credits;
+        self
+    }
+
+    pub fn with_per_page(mut self, per_page: usize) -> Self {
+        self.per_page = per_page.max(1);
+        self
+    }
+
+    pub fn with_delivery_status(mut self, status: &str) -> Self {
+        self.delivery_status = status.to_string();
+        self
+    }
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig::new("test_api_key", "test@email.com")
+    }
+}
+
+/// `Fault` is a failure the mock server injects instead of (or before) normal handling
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Delay the response, then handle the request normally
+    Latency(Duration),
+    /// Respond with this HTTP status and an error body
+    Status(u16),
+    /// Respond `200 OK` with a truncated JSON body
+    MalformedBody,
+}
+
+/// `FaultRule` applies a `Fault` to one endpoint (or all) for a number of requests (or forever)
+#[derive(Debug, Clone)]
+pub struct FaultRule {
+    pub endpoint: Option<ApiEndpoint>,
+    pub fault: Fault,
+    pub remaining: Option<usize>,
+}
+
+impl FaultRule {
+    /// Applies `fault` to every request on every endpoint
+    pub fn always(fault: Fault) -> Self {
+        FaultRule {
+            endpoint: None,
+            fault,
+            remaining: None,
+        }
+    }
+
+    /// Applies `fault` to the next `count` matching requests; a count of 0 never applies
+    pub fn times(fault: Fault, count: usize) -> Self {
+        FaultRule {
+            endpoint: None,
+            fault,
+            remaining: Some(count),
+        }
+    }
+
+    pub fn on(mut self, endpoint: ApiEndpoint) -> Self {
+        self.endpoint = Some(endpoint);
+        self
+    }
+}
+
+#[derive(Debug)]
+struct MockState {
+    config: MockConfig,
+    base_url: String,
+    credits: i64,
+    messages: Vec<MessageSent>,
+    next_id: i64,
+    next_request_id: i64,
+    blacklist: Vec<String>,
+    faults: Vec<FaultRule>,
+}
+
+impl MockState {
+    /// Takes the first fault matching `endpoint`, consuming one use of it.
+    /// Rules with no uses left are dropped first.
+    fn take_fault(&mut self, endpoint: &ApiEndpoint) -> Option<Fault> {
+        self.faults.retain(|rule| rule.remaining != Some(0));
+        let index = self
+            .faults
+            .iter()
+            .position(|rule| rule.endpoint.as_ref().is_none_or(|e| e == endpoint))?;
+        let rule = &mut self.faults[index];
+        let fault = rule.fault.clone();
+        if let Some(remaining) = rule.remaining.as_mut() {
+            *remaining -= 1;
+            if *remaining == 0 {
+                self.faults.remove(index);
+            }
+        }
+        Some(fault)
+    }
+}
+
+type SharedState = Arc<Mutex<MockState>>;
+
+/// `MockServer` is a running mock of the UjumbeSMS API bound to a local port
+pub struct MockServer {
+    addr: SocketAddr,
+    state: SharedState,
+    shutdown: Option<oneshot::Sender<()>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Starts a server on a random local port
+    pub async fn start(config: MockConfig) -> std::io::Result<Self> {
+        MockServer::bind(config, SocketAddr::from(([127, 0, 0, 1], 0))).await
+    }
+
+    pub async fn bind(config: MockConfig, addr: SocketAddr) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(MockState {
+            credits: config.credits,
+            config,
+            base_url: format!("http://{addr}"),
+            messages: Vec::new(),
+            next_id: 1,
+            next_request_id: 10001,
+            blacklist: Vec::new(),
+            faults: Vec::new(),
+        }));
+
+        let app = Router::new()
+            .route(ApiEndpoint::Messaging.as_str(), post(messaging))
+            .route(ApiEndpoint::Balances.as_str(), post(balance))
+            .route(ApiEndpoint::Messages.as_str(), post(messages))
+            .with_state(state.clone());
+
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            let _ = axum::serve(listener, app)
+                .with_graceful_shutdown(async {
+                    let _ = shutdown_rx.await;
+                })
+                .await;
+        });
+
+        Ok(MockServer {
+            addr,
+            state,
+            shutdown: Some(shutdown),
+            task,
+        })
+    }
+
+    /// Base URL to pass to `UjumbeSmsConfig::with_base_url`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn credits(&self) -> i64 {
+        self.lock().credits
+    }
+
+    pub fn set_credits(&self, credits: i64) {
+        self.lock().credits = credits;
+    }
+
+    /// Every message sent so far, oldest first
+    pub fn messages(&self) -> Vec<MessageSent> {
+        self.lock().messages.clone()
+    }
+
+    /// Changes the delivery status of a stored message, as a delivery report would
+    pub fn set_status(&self, id: i64, status: &str) -> bool {
+        let mut state = self.lock();
+        let now = timestamp();
+        match state.messages.iter_mut().find(|msg| msg.id == id) {
+            Some(message) => {
+                message.status = status.to_string();
+                message.updated_at = now;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Messages to `number` are stored with a `Blacklisted` status and cost no credits
+    pub fn blacklist(&self, number: &str) {
+        self.lock().blacklist.push(normalize_number(number));
+    }
+
+    pub fn inject(&self, rule: FaultRule) {
+        self.lock().faults.push(rule);
+    }
+
+    pub fn clear_faults(&self) {
+        self.lock().faults.clear();
+    }
+
+    /// Stops the server and waits for it to exit
+    pub async fn shutdown(mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        let _ = (&mut self.task).await;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().expect("mock state lock poisoned")
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn timestamp() -> String {
+    ujumbe_sms::sending_window::nairobi_now()
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+fn date_time() -> serde_json::Value {
+    json!({
+        "date": timestamp(),
+        "timezone_type": 3,
+        "timezone": "Africa/Nairobi"
+    })
+}
+
+fn error(status: StatusCode, code: &str, description: &str) -> Response {
+    (
+        status,
+        axum::Json(json!({
+            "status": { "code": code, "type": "error", "description": description }
+        })),
+    )
+        .into_response()
+}
+
+/// Applies injected faults and checks credentials; `Err` short-circuits the handler
+async fn preflight(
+    state: &SharedState,
+    endpoint: ApiEndpoint,
+    headers: &HeaderMap,
+) -> Result<(), Response> {
+    let fault = state
+        .lock()
+        .expect("mock state lock poisoned")
+        .take_fault(&endpoint);
+    match fault {
+        Some(Fault::Latency(delay)) => tokio::time::sleep(delay).await,
+        Some(Fault::Status(code)) => {
+            let status = StatusCode::from_u16(code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(error(status, "1500", "Injected fault"));
+        }
+        Some(Fault::MalformedBody) => {
+            return Err((
+                StatusCode::OK,
+                [("content-type", "application/json")],
+                r#"{"status": {"code": "1008", "type": "succ"#,
+            )
+                .into_response());
+        }
+        None => {}
+    }
+
+    let state = state.lock().expect("mock state lock poisoned");
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    if header("X-Authorization") != Some(state.config.api_key.as_str())
+        || header("Email") != Some(state.config.email.as_str())
+    {
+        return Err(error(
+            StatusCode::UNAUTHORIZED,
+            "1001",
+            "Invalid API credentials",
+        ));
+    }
+    Ok(())
+}
+
+async fn messaging(State(state): State<SharedState>, headers: HeaderMap, body: Bytes) -> Response {
+    if let Err(response) = preflight(&state, ApiEndpoint::Messaging, &headers).await {
+        return response;
+    }
+
+    let request: MessageRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "1002",
+                &format!("Invalid body: {e}"),
+            )
+        }
+    };
+    if request.data.is_empty() {
+        return error(StatusCode::BAD_REQUEST, "1002", "No message bags supplied");
+    }
+    if let Some(bag) =
+        request.data.iter().map(|c| &c.message_bag).find(|bag| {
+            bag.recipient_count() == 0 || bag.message.is_empty() || bag.sender.is_empty()
+        })
+    {
+        return error(
+            StatusCode::BAD_REQUEST,
+            "1002",
+            &format!("Incomplete message bag for '{}'", bag.numbers),
+        );
+    }
+
+    let mut state = state.lock().expect("mock state lock poisoned");
+    let rate = i64::from(state.config.rate);
+    let cost: i64 = request
+        .data
+        .iter()
+        .map(|c| {
+            let bag = &c.message_bag;
+            let billable = split_numbers(&bag.numbers)
+                .filter(|n| !state.blacklist.contains(&normalize_number(n)))
+                .count();
+            (billable * bag.segment_count()) as i64 * rate
+        })
+        .sum();
+    if cost > state.credits {
+        return error(StatusCode::PAYMENT_REQUIRED, "1003", "Insufficient credits");
+    }
+    state.credits -= cost;
+
+    let now = timestamp();
+    let mut recipients = 0;
+    for container in &request.data {
+        let bag = &container.message_bag;
+        let request_id = state.next_request_id;
+        state.next_request_id += 1;
+        for number in split_numbers(&bag.numbers) {
+            let normalized = normalize_number(number);
+            let status = if state.blacklist.contains(&normalized) {
+                "Blacklisted".to_string()
+            } else {
+                state.config.delivery_status.clone()
+            };
+            let id = state.next_id;
+            state.next_id += 1;
+            state.messages.push(MessageSent {
+                id,
+                request_id,
+                number: format!("+{normalized}"),
+                message: bag.message.clone(),
+                user_id: 1,
+                sender_id: bag.sender.clone(),
+                transaction_id: format!("mock-{request_id}-{id}"),
+                message_count: bag.segment_count() as i32,
+                status,
+                flag: "API|".to_string(),
+                created_at: now.clone(),
+                updated_at: now.clone(),
+                scheduled_date: now.clone(),
+            });
+            recipients += 1;
+        }
+    }
+
+    axum::Json(json!({
+        "status": {
+            "code": "1008",
+            "type": "success",
+            "description": "Your messages have been queued"
+        },
+        "meta": {
+            "recipients": recipients,
+            "credits_deducted": cost,
+            "available_credits": state.credits.to_string(),
+            "user_email": state.config.email,
+            "date_time": date_time()
+        }
+    }))
+    .into_response()
+}
+
+async fn balance(State(state): State<SharedState>, headers: HeaderMap) -> Response {
+    if let Err(response) = preflight(&state, ApiEndpoint::Balances, &headers).await {
+        return response;
+    }
+
+    let state = state.lock().expect("mock state lock poisoned");
+    axum::Json(json!({
+        "status": { "code": "1008", "type": "success", "description": "Balance inquiry" },
+        "meta": {
+            "user": state.config.email,
+            "credits": state.credits,
+            "rate": state.config.rate,
+            "date_time": date_time()
+        }
+    }))
+    .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct PageQuery {
+    page: Option<usize>,
+}
+
+/// History is served newest first, like the real service
+async fn messages(
+    State(state): State<SharedState>,
+    Query(query): Query<PageQuery>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(response) = preflight(&state, ApiEndpoint::Messages, &headers).await {
+        return response;
+    }
+
+    let state = state.lock().expect("mock state lock poisoned");
+    let per_page = state.config.per_page;
+    let total = state.messages.len();
+    let last_page = total.div_ceil(per_page).max(1);
+    let page = query.page.unwrap_or(1).max(1);
+    let skip = (page - 1) * per_page;
+    let data: Vec<MessageSent> = state
+        .messages
+        .iter()
+        .rev()
+        .skip(skip)
+        .take(per_page)
+        .cloned()
+        .collect();
+
+    let page_url = |page: usize| {
+        format!(
+            "{}{}?page={page}",
+            state.base_url,
+            ApiEndpoint::Messages.as_str()
+        )
+    };
+    let items = Items {
+        total: total as i32,
+        per_page: per_page as i32,
+        current_page: page as i32,
+        last_page: last_page as i32,
+        next_page_url: (page < last_page).then(|| page_url(page + 1)),
+        prev_page_url: (page > 1).then(|| page_url(page - 1)),
+        from: if data.is_empty() { 0 } else { skip as i32 + 1 },
+        to: (skip + data.len()) as i32,
+        data,
+    };
+
+    axum::Json(json!({
+        "status": { "code": "1008", "type": "success", "description": "Query Success" },
+        "meta": { "user": state.config.email, "date_time": date_time() },
+        "items": items
+    }))
+    .into_response()
+}
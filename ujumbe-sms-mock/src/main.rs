@@ -0,0 +1,59 @@
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use ujumbe_sms_mock::{MockConfig, MockServer};
+
+const USAGE: &str = "Usage: ujumbe-sms-mock [--port PORT] [--api-key KEY] [--email EMAIL] [--credits N] [--per-page N]";
+
+fn parse_args() -> Result<(MockConfig, u16), String> {
+    let mut config = MockConfig::default();
+    let mut port = 8080;
+    let mut args = std::env::args().skip(1);
+
+    while let Some(flag) = args.next() {
+        if flag == "--help" || flag == "-h" {
+            return Err(USAGE.to_string());
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{flag} needs a value\n{USAGE}"))?;
+        let invalid = |_| format!("invalid value '{value}' for {flag}");
+        match flag.as_str() {
+            "--port" => port = value.parse().map_err(invalid)?,
+            "--api-key" => config.api_key = value,
+            "--email" => config.email = value,
+            "--credits" => config.credits = value.parse().map_err(invalid)?,
+            "--per-page" => config = config.with_per_page(value.parse().map_err(invalid)?),
+            _ => return Err(format!("unknown argument '{flag}'\n{USAGE}")),
+        }
+    }
+
+    Ok((config, port))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let (config, port) = match parse_args() {
+        Ok(parsed) => parsed,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (api_key, email) = (config.api_key.clone(), config.email.clone());
+    let server = match MockServer::bind(config, SocketAddr::from(([127, 0, 0, 1], port))).await {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("failed to bind port {port}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!("UjumbeSMS mock listening on {}", server.url());
+    println!("X-Authorization: {api_key}");
+    println!("Email: {email}");
+
+    let _ = tokio::signal::ctrl_c().await;
+    server.shutdown().await;
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,121 @@
+use std::time::{Duration, Instant};
+use ujumbe_sms::client::ApiEndpoint;
+use ujumbe_sms::{MessageRequest, UjumbeSmsClient, UjumbeSmsConfig, UjumbeSmsError};
+use ujumbe_sms_mock::{Fault, FaultRule, MockConfig, MockServer};
+
+fn client_for(server: &MockServer) -> UjumbeSmsClient {
+    let config = UjumbeSmsConfig::new("test_api_key".to_string(), "test@email.com".to_string())
+        .with_base_url(server.url());
+    UjumbeSmsClient::new(config).unwrap()
+}
+
+#[tokio::test]
+async fn test_send_deducts_credits_and_feeds_history() {
+    let server = MockServer::start(MockConfig::default().with_credits(100).with_per_page(2))
+        .await
+        .unwrap();
+    let client = client_for(&server);
+
+    let mut request = MessageRequest::new();
+    request.add_message_bag(
+        "254711111111,0722222222".to_string(),
+        "First".to_string(),
+        "UjumbeSMS".to_string(),
+    );
+    request.add_message_bag(
+        "254733333333".to_string(),
+        "a".repeat(200),
+        "UjumbeSMS".to_string(),
+    );
+
+    let response = client.send_messages(request).await.unwrap();
+    let meta = response.meta.unwrap();
+    assert_eq!(meta.recipients, 3);
+    assert_eq!(meta.credits_deducted, 4);
+    assert_eq!(meta.available_credits, "96");
+    assert_eq!(client.balance().await.unwrap().meta.unwrap().credits, 96);
+
+    let first = client.get_messages_history_page(1).await.unwrap();
+    assert_eq!(first.items.total, 3);
+    assert_eq!(first.items.last_page, 2);
+    assert_eq!(first.items.data[0].number, "+254733333333");
+    assert!(first.items.next_page_url.is_some());
+
+    let second = client.get_messages_history_page(2).await.unwrap();
+    assert_eq!(second.items.data.len(), 1);
+    assert_eq!(second.items.data[0].number, "+254711111111");
+    assert_eq!(
+        second.items.data[0].request_id,
+        first.items.data[1].request_id
+    );
+}
+
+#[tokio::test]
+async fn test_rejects_bad_credentials_and_insufficient_credits() {
+    let server = MockServer::start(MockConfig::default().with_credits(1))
+        .await
+        .unwrap();
+
+    let config = UjumbeSmsConfig::new("wrong".to_string(), "test@email.com".to_string())
+        .with_base_url(server.url());
+    let result = UjumbeSmsClient::new(config).unwrap().balance().await;
+    assert!(matches!(result, Err(UjumbeSmsError::ApiError(code, _)) if code.starts_with("401")));
+
+    let result = client_for(&server)
+        .send_single_message("254711111111,254722222222", "Hello", "UjumbeSMS")
+        .await;
+    assert!(matches!(result, Err(UjumbeSmsError::ApiError(code, _)) if code.starts_with("402")));
+    assert_eq!(server.credits(), 1);
+}
+
+#[tokio::test]
+async fn test_blacklisted_numbers_and_status_updates() {
+    let server = MockServer::start(MockConfig::default().with_delivery_status("SENT"))
+        .await
+        .unwrap();
+    server.blacklist("0722222222");
+    let client = client_for(&server);
+
+    let response = client
+        .send_single_message("254711111111,254722222222", "Hello", "UjumbeSMS")
+        .await
+        .unwrap();
+    assert_eq!(response.meta.unwrap().credits_deducted, 1);
+
+    let sent = server.messages();
+    assert!(server.set_status(sent[0].id, "DeliveredToTerminal"));
+
+    let history = client.get_messages_history().await.unwrap();
+    assert_eq!(history.get_failed_messages().len(), 1);
+    assert_eq!(history.get_delivered_messages().len(), 1);
+}
+
+#[tokio::test]
+async fn test_fault_injection() {
+    let server = MockServer::start(MockConfig::default()).await.unwrap();
+    let client = client_for(&server);
+
+    // A rule with no uses never applies
+    server.inject(FaultRule::times(Fault::Status(500), 0));
+    server.inject(FaultRule::times(Fault::Status(503), 1).on(ApiEndpoint::Balances));
+    let result = client.balance().await;
+    assert!(matches!(result, Err(UjumbeSmsError::ApiError(code, _)) if code.starts_with("503")));
+    assert!(client.balance().await.is_ok());
+
+    // Bodies are decoded after the service stack, so a malformed one is a serialization error
+    server.inject(FaultRule::times(Fault::MalformedBody, 1));
+    assert!(matches!(
+        client.balance().await,
+        Err(UjumbeSmsError::SerializationError(_))
+    ));
+
+    server.inject(FaultRule::always(Fault::Latency(Duration::from_millis(
+        200,
+    ))));
+    let started = Instant::now();
+    assert!(client.balance().await.is_ok());
+    assert!(started.elapsed() >= Duration::from_millis(200));
+
+    server.clear_faults();
+    server.shutdown().await;
+}